@@ -0,0 +1,3 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_entries_content_hash ON entries (content_hash);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS classification_feedback (
+  id UUID PRIMARY KEY,
+  entry_id UUID REFERENCES entries (id) ON DELETE SET NULL,
+  model TEXT,
+  label TEXT NOT NULL,
+  correct BOOLEAN NOT NULL,
+  corrected_label TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_classification_feedback_entry_id ON classification_feedback (entry_id);
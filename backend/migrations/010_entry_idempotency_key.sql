@@ -0,0 +1,3 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS idempotency_key TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_entries_idempotency_key ON entries (idempotency_key);
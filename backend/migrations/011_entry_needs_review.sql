@@ -0,0 +1 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS needs_review BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS is_pinned BOOLEAN NOT NULL DEFAULT FALSE;
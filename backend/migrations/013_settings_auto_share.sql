@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN IF NOT EXISTS auto_share BOOLEAN NOT NULL DEFAULT FALSE;
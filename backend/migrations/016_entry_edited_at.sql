@@ -0,0 +1 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS edited_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS source_entry_id UUID REFERENCES entries(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_entries_source_entry_id ON entries (source_entry_id);
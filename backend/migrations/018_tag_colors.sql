@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS tag_colors (
+  tag TEXT PRIMARY KEY,
+  color TEXT NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS source TEXT NOT NULL DEFAULT 'upload';
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS lat DOUBLE PRECISION;
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS lng DOUBLE PRECISION;
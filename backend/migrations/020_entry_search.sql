@@ -0,0 +1,4 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS search_vector tsvector
+  GENERATED ALWAYS AS (to_tsvector('english', label || ' ' || description)) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_entries_search_vector ON entries USING GIN (search_vector);
@@ -0,0 +1 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS classification_status TEXT NOT NULL DEFAULT 'classified';
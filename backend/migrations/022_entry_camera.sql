@@ -0,0 +1,2 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS camera_make TEXT;
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS camera_model TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS verified BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS verified_by TEXT;
@@ -0,0 +1 @@
+ALTER TABLE settings ADD COLUMN IF NOT EXISTS empty_message TEXT;
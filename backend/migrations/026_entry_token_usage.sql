@@ -0,0 +1,2 @@
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS input_tokens INTEGER;
+ALTER TABLE entries ADD COLUMN IF NOT EXISTS output_tokens INTEGER;
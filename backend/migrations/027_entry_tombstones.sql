@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS entry_tombstones (
+  id UUID PRIMARY KEY,
+  purged_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_entry_tombstones_purged_at ON entry_tombstones (purged_at);
@@ -0,0 +1,48 @@
+//! Minimal RFC 4180 CSV reader for imports.
+//!
+//! Handles quoted fields (with `""` escapes and embedded newlines), CRLF line endings
+//! and a leading byte-order mark, which covers the exports we accept.
+
+/// Splits `text` into records of fields. Blank lines are skipped.
+pub fn parse(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if in_quotes {
+            match ch {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(ch),
+            }
+            continue;
+        }
+        match ch {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' | '\r' => end_record(&mut records, &mut record, &mut field),
+            _ => field.push(ch),
+        }
+    }
+    if in_quotes {
+        return Err(format!("unterminated quoted field in record {}", records.len() + 1));
+    }
+    end_record(&mut records, &mut record, &mut field);
+    Ok(records)
+}
+
+fn end_record(records: &mut Vec<Vec<String>>, record: &mut Vec<String>, field: &mut String) {
+    record.push(std::mem::take(field));
+    let record = std::mem::take(record);
+    if !(record.len() == 1 && record[0].is_empty()) {
+        records.push(record);
+    }
+}
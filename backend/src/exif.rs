@@ -0,0 +1,457 @@
+//! Reading and removing image metadata without re-encoding.
+//!
+//! Uploads straight from a phone carry EXIF (GPS, device details) that would otherwise be
+//! served publicly under `/media`. Stripping works on the container, so pixels are never
+//! recompressed: JPEG segments, PNG chunks and WebP RIFF chunks holding metadata are
+//! dropped and everything else is copied as-is.
+
+/// What we keep from an upload's EXIF before it's stripped.
+#[derive(Default)]
+pub struct Metadata {
+    /// Camera manufacturer (`Make`).
+    pub make: Option<String>,
+    /// Camera model (`Model`).
+    pub model: Option<String>,
+    /// Signed decimal degrees from the GPS IFD; both set or both `None`.
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+/// Reads the EXIF block of a JPEG, PNG or WebP file; empty when there is none.
+pub fn read(bytes: &[u8], mime: &str) -> Metadata {
+    let Some(tiff) = find_exif(bytes, mime).and_then(Tiff::new) else {
+        return Metadata::default();
+    };
+    let ifd0 = tiff.ifd0();
+    let (lat, lng) = match ifd0.and_then(|ifd| tiff.gps(ifd)) {
+        Some((lat, lng)) => (Some(lat), Some(lng)),
+        None => (None, None),
+    };
+    Metadata {
+        make: ifd0.and_then(|ifd| tiff.ascii(ifd, 0x010F)),
+        model: ifd0.and_then(|ifd| tiff.ascii(ifd, 0x0110)),
+        lat,
+        lng,
+    }
+}
+
+/// The TIFF structure inside a file's EXIF block.
+fn find_exif<'a>(bytes: &'a [u8], mime: &str) -> Option<&'a [u8]> {
+    match mime {
+        "image/jpeg" => {
+            let mut pos = 2;
+            while bytes.get(pos) == Some(&0xFF) {
+                let marker = *bytes.get(pos + 1)?;
+                // EXIF sits in the header; stop at the scan or a marker without a length.
+                if marker == 0xDA || marker == 0xD9 || (0xD0..=0xD7).contains(&marker) {
+                    return None;
+                }
+                let length = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+                let segment = bytes.get(pos + 4..pos + 2 + length)?;
+                if marker == 0xE1
+                    && let Some(tiff) = segment.strip_prefix(b"Exif\0\0")
+                {
+                    return Some(tiff);
+                }
+                pos += 2 + length;
+            }
+            None
+        }
+        "image/png" => {
+            let mut pos = 8;
+            loop {
+                let length = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+                let kind = bytes.get(pos + 4..pos + 8)?;
+                match kind {
+                    b"eXIf" => return bytes.get(pos + 8..(pos + 8).checked_add(length)?),
+                    b"IDAT" | b"IEND" => return None,
+                    _ => pos = pos.checked_add(12 + length)?,
+                }
+            }
+        }
+        "image/webp" => {
+            let mut pos = 12;
+            loop {
+                let kind = bytes.get(pos..pos + 4)?;
+                let length = u32::from_le_bytes(bytes.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+                if kind == b"EXIF" {
+                    let data = bytes.get(pos + 8..(pos + 8).checked_add(length)?)?;
+                    // Some writers keep the JPEG-style prefix.
+                    return Some(data.strip_prefix(b"Exif\0\0").unwrap_or(data));
+                }
+                pos = pos.checked_add(8 + length + (length & 1))?;
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Removes EXIF, XMP and text metadata from a JPEG, PNG or WebP file.
+///
+/// A JPEG keeps its orientation in a minimal replacement EXIF block so it doesn't display
+/// rotated. Returns `None` for other formats or when the file can't be parsed.
+pub fn strip(bytes: &[u8], mime: &str) -> Option<Vec<u8>> {
+    match mime {
+        "image/jpeg" => strip_jpeg(bytes),
+        "image/png" => strip_png(bytes),
+        "image/webp" => strip_webp(bytes),
+        _ => None,
+    }
+}
+
+fn strip_jpeg(bytes: &[u8]) -> Option<Vec<u8>> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&[0xFF, 0xD8]);
+    let mut orientation = None;
+    let mut pos = 2;
+    loop {
+        if *bytes.get(pos)? != 0xFF {
+            return None;
+        }
+        // Any number of 0xFF fill bytes may precede a marker.
+        while *bytes.get(pos + 1)? == 0xFF {
+            pos += 1;
+        }
+        let marker = bytes[pos + 1];
+        match marker {
+            // End of image, or markers without a length.
+            0xD9 => {
+                out.extend_from_slice(&[0xFF, 0xD9]);
+                return Some(out);
+            }
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&bytes[pos..pos + 2]);
+                pos += 2;
+                continue;
+            }
+            _ => {}
+        }
+        let length = u16::from_be_bytes([*bytes.get(pos + 2)?, *bytes.get(pos + 3)?]) as usize;
+        if length < 2 {
+            return None;
+        }
+        let end = pos + 2 + length;
+        let segment = bytes.get(pos..end)?;
+        match marker {
+            // APP1 (EXIF, XMP), APP13 (IPTC) and comments.
+            0xE1 | 0xED | 0xFE => {
+                if marker == 0xE1
+                    && let Some(tiff) = segment[4..].strip_prefix(b"Exif\0\0")
+                {
+                    orientation = orientation.or_else(|| Tiff::new(tiff)?.orientation());
+                }
+            }
+            // Start of scan: the compressed data runs to the end of the file.
+            0xDA => {
+                if let Some(value) = orientation.filter(|value| *value != 1) {
+                    insert_orientation(&mut out, value);
+                }
+                out.extend_from_slice(&bytes[pos..]);
+                return Some(out);
+            }
+            _ => out.extend_from_slice(segment),
+        }
+        pos = end;
+    }
+}
+
+/// Inserts an APP1 segment holding only an EXIF orientation, right after SOI / APP0.
+fn insert_orientation(out: &mut Vec<u8>, orientation: u16) {
+    let mut segment = vec![0xFF, 0xE1, 0x00, 0x22];
+    segment.extend_from_slice(b"Exif\0\0");
+    // Big-endian TIFF header, IFD0 at offset 8 with a single SHORT entry.
+    segment.extend_from_slice(b"MM\0\x2A\0\0\0\x08");
+    segment.extend_from_slice(&[0x00, 0x01]);
+    segment.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+    segment.extend_from_slice(&orientation.to_be_bytes());
+    segment.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+    // Keep a leading JFIF APP0 first, as decoders expect.
+    let at = if out.len() >= 6 && out[2..4] == [0xFF, 0xE0] {
+        4 + u16::from_be_bytes([out[4], out[5]]) as usize
+    } else {
+        2
+    };
+    out.splice(at..at, segment);
+}
+
+fn strip_png(bytes: &[u8]) -> Option<Vec<u8>> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !bytes.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(SIGNATURE);
+    let mut pos = SIGNATURE.len();
+    loop {
+        let length = u32::from_be_bytes(bytes.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let kind = bytes.get(pos + 4..pos + 8)?;
+        // Length, type, data and CRC.
+        let end = pos.checked_add(12 + length)?;
+        let chunk = bytes.get(pos..end)?;
+        if !matches!(kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(chunk);
+        }
+        if kind == b"IEND" {
+            return Some(out);
+        }
+        pos = end;
+    }
+}
+
+fn strip_webp(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WEBP" {
+        return None;
+    }
+    let riff_end = (8 + u32::from_le_bytes(bytes[4..8].try_into().ok()?) as usize).min(bytes.len());
+    let mut out = Vec::with_capacity(bytes.len());
+    out.extend_from_slice(&bytes[0..12]);
+    let mut pos = 12;
+    while pos + 8 <= riff_end {
+        let kind = &bytes[pos..pos + 4];
+        let length = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into().ok()?) as usize;
+        // Chunks are padded to an even size.
+        let end = pos.checked_add(8 + length + (length & 1))?.min(riff_end);
+        let chunk = bytes.get(pos..end)?;
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if chunk.len() > 8 => {
+                // Clear the "has EXIF" and "has XMP" flags.
+                let start = out.len();
+                out.extend_from_slice(chunk);
+                out[start + 8] &= !0x0C;
+            }
+            _ => out.extend_from_slice(chunk),
+        }
+        pos = end;
+    }
+    let riff_size = u32::try_from(out.len() - 8).ok()?;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
+
+/// A TIFF structure as embedded in EXIF blocks.
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(0..2)? {
+            b"MM" => true,
+            b"II" => false,
+            _ => return None,
+        };
+        let tiff = Self { data, big_endian };
+        (tiff.u16_at(2)? == 42).then_some(tiff)
+    }
+
+    fn u16_at(&self, at: usize) -> Option<u16> {
+        let raw: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u16::from_be_bytes(raw),
+            false => u16::from_le_bytes(raw),
+        })
+    }
+
+    fn u32_at(&self, at: usize) -> Option<u32> {
+        let raw: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(match self.big_endian {
+            true => u32::from_be_bytes(raw),
+            false => u32::from_le_bytes(raw),
+        })
+    }
+
+    /// Offset of the 12-byte entry for `tag` in the IFD at `ifd`.
+    fn find(&self, ifd: usize, tag: u16) -> Option<usize> {
+        let count = self.u16_at(ifd)? as usize;
+        (0..count)
+            .map(|index| ifd + 2 + index * 12)
+            .find(|entry| self.u16_at(*entry) == Some(tag))
+    }
+
+    fn ifd0(&self) -> Option<usize> {
+        Some(self.u32_at(4)? as usize)
+    }
+
+    /// An ASCII value, trimmed of padding; `None` when missing or blank.
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let entry = self.find(ifd, tag)?;
+        if self.u16_at(entry + 2)? != 2 {
+            return None;
+        }
+        let count = self.u32_at(entry + 4)? as usize;
+        // Values of up to four bytes are stored in place of the offset.
+        let start = match count {
+            0..=4 => entry + 8,
+            _ => self.u32_at(entry + 8)? as usize,
+        };
+        let raw = self.data.get(start..start.checked_add(count)?)?;
+        let text = String::from_utf8_lossy(raw);
+        let text = text.trim_matches(|ch: char| ch == '\0' || ch.is_whitespace());
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    /// Latitude and longitude, negated for `S` / `W` references.
+    fn gps(&self, ifd0: usize) -> Option<(f64, f64)> {
+        let pointer = self.find(ifd0, 0x8825)?;
+        let gps = self.u32_at(pointer + 8)? as usize;
+        let lat = self.degrees(gps, 0x0002)?;
+        let lng = self.degrees(gps, 0x0004)?;
+        let lat = match self.ascii(gps, 0x0001)?.as_str() {
+            "N" | "n" => lat,
+            "S" | "s" => -lat,
+            _ => return None,
+        };
+        let lng = match self.ascii(gps, 0x0003)?.as_str() {
+            "E" | "e" => lng,
+            "W" | "w" => -lng,
+            _ => return None,
+        };
+        // Unset fixes are often written as 0/0 or out-of-range values.
+        let valid = lat.abs() <= 90.0 && lng.abs() <= 180.0 && (lat, lng) != (0.0, 0.0);
+        valid.then_some((lat, lng))
+    }
+
+    /// Three RATIONALs (degrees, minutes, seconds) as decimal degrees.
+    fn degrees(&self, ifd: usize, tag: u16) -> Option<f64> {
+        let entry = self.find(ifd, tag)?;
+        if self.u16_at(entry + 2)? != 5 || self.u32_at(entry + 4)? != 3 {
+            return None;
+        }
+        let start = self.u32_at(entry + 8)? as usize;
+        let mut total = 0.0;
+        for (index, scale) in [1.0, 60.0, 3600.0].into_iter().enumerate() {
+            let numerator = self.u32_at(start + index * 8)?;
+            let denominator = self.u32_at(start + index * 8 + 4)?;
+            if denominator == 0 {
+                // 0/0 for minutes or seconds just means "not given".
+                if numerator != 0 || index == 0 {
+                    return None;
+                }
+                continue;
+            }
+            total += numerator as f64 / denominator as f64 / scale;
+        }
+        Some(total)
+    }
+
+    fn orientation(&self) -> Option<u16> {
+        let entry = self.find(self.ifd0()?, 0x0112)?;
+        // A single SHORT sits left-aligned in the value field.
+        self.u16_at(entry + 8).filter(|value| (1..=8).contains(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Dms = [(u32, u32); 3];
+
+    /// A minimal TIFF block: IFD0 with `Make` and a GPS pointer, then the GPS IFD.
+    fn tiff(big_endian: bool, lat_ref: &str, lat: Dms, lng_ref: &str, lng: Dms) -> Vec<u8> {
+        let u16b = |value: u16| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let u32b = |value: u32| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        // Layout: header (8), IFD0 with 2 entries (30), GPS IFD with 4 entries (54), rationals.
+        let ifd0 = 8u32;
+        let gps = ifd0 + 2 + 2 * 12 + 4;
+        let rationals = gps + 2 + 4 * 12 + 4;
+        let mut out = Vec::new();
+        out.extend_from_slice(if big_endian { b"MM" } else { b"II" });
+        out.extend_from_slice(&u16b(42));
+        out.extend_from_slice(&u32b(ifd0));
+
+        let entry = |out: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: [u8; 4]| {
+            out.extend_from_slice(&u16b(tag));
+            out.extend_from_slice(&u16b(kind));
+            out.extend_from_slice(&u32b(count));
+            out.extend_from_slice(&value);
+        };
+        let ascii = |text: &str| {
+            let mut value = [0u8; 4];
+            value[..text.len()].copy_from_slice(text.as_bytes());
+            value
+        };
+        out.extend_from_slice(&u16b(2));
+        entry(&mut out, 0x010F, 2, 4, ascii("Fuj"));
+        entry(&mut out, 0x8825, 4, 1, u32b(gps));
+        out.extend_from_slice(&u32b(0));
+
+        out.extend_from_slice(&u16b(4));
+        entry(&mut out, 0x0001, 2, 2, ascii(lat_ref));
+        entry(&mut out, 0x0002, 5, 3, u32b(rationals));
+        entry(&mut out, 0x0003, 2, 2, ascii(lng_ref));
+        entry(&mut out, 0x0004, 5, 3, u32b(rationals + 24));
+        out.extend_from_slice(&u32b(0));
+
+        for (numerator, denominator) in lat.into_iter().chain(lng) {
+            out.extend_from_slice(&u32b(numerator));
+            out.extend_from_slice(&u32b(denominator));
+        }
+        out
+    }
+
+    fn gps(data: &[u8]) -> Option<(f64, f64)> {
+        let tiff = Tiff::new(data)?;
+        tiff.gps(tiff.ifd0()?)
+    }
+
+    const LAT: Dms = [(51, 1), (30, 1), (2610, 100)];
+    const LNG: Dms = [(0, 1), (7, 1), (3960, 100)];
+
+    fn close(actual: (f64, f64), expected: (f64, f64)) -> bool {
+        (actual.0 - expected.0).abs() < 1e-6 && (actual.1 - expected.1).abs() < 1e-6
+    }
+
+    #[test]
+    fn gps_signs_follow_the_references() {
+        let (lat, lng) = (51.0 + 30.0 / 60.0 + 26.1 / 3600.0, 7.0 / 60.0 + 39.6 / 3600.0);
+        for big_endian in [false, true] {
+            for (lat_ref, lng_ref, expected) in [
+                ("N", "E", (lat, lng)),
+                ("S", "E", (-lat, lng)),
+                ("N", "W", (lat, -lng)),
+                ("s", "w", (-lat, -lng)),
+            ] {
+                let found = gps(&tiff(big_endian, lat_ref, LAT, lng_ref, LNG)).unwrap();
+                assert!(close(found, expected), "{lat_ref}{lng_ref}: {found:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn gps_rejects_unset_and_invalid_fixes() {
+        let zero: Dms = [(0, 1), (0, 1), (0, 1)];
+        assert_eq!(gps(&tiff(false, "N", zero, "E", zero)), None);
+        // 0/0 minutes and seconds mean "not given", but 0/0 degrees is no fix at all.
+        assert_eq!(gps(&tiff(false, "N", [(0, 0), (0, 0), (0, 0)], "E", LNG)), None);
+        assert!(gps(&tiff(false, "N", [(12, 1), (0, 0), (0, 0)], "E", LNG)).is_some());
+        assert_eq!(gps(&tiff(false, "N", [(91, 1), (0, 1), (0, 1)], "E", LNG)), None);
+        assert_eq!(gps(&tiff(false, "X", LAT, "E", LNG)), None);
+    }
+
+    #[test]
+    fn read_finds_gps_and_make_in_a_jpeg() {
+        let block = tiff(false, "S", LAT, "W", LNG);
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+        jpeg.extend_from_slice(&((block.len() + 8) as u16).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&block);
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+
+        let metadata = read(&jpeg, "image/jpeg");
+        assert_eq!(metadata.make.as_deref(), Some("Fuj"));
+        assert!(metadata.lat.unwrap() < 0.0 && metadata.lng.unwrap() < 0.0);
+    }
+}
@@ -1,36 +1,157 @@
+mod csv;
+mod exif;
+mod pdf;
+#[cfg(test)]
+mod tests;
+mod zip;
+
 use axum::{
-    extract::{DefaultBodyLimit, Multipart, Path, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    body::Body,
+    extract::{DefaultBodyLimit, Multipart, Path, Query, RawQuery, Request, State},
+    handler::{Handler, HandlerWithoutStateExt},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
     routing::{get, post},
     Json, Router,
 };
 use base64::Engine;
 use bytes::Bytes;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, Timelike, Utc};
+use futures_util::{StreamExt, future::BoxFuture};
 use serde::{Deserialize, Serialize};
-use sqlx::{postgres::PgPoolOptions, PgPool, Row};
+use sha2::{Digest, Sha256};
+use sqlx::{postgres::PgPoolOptions, PgPool, Postgres, QueryBuilder, Row};
+use image::{DynamicImage, ImageFormat};
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Cursor,
     net::SocketAddr,
     path::PathBuf,
-    sync::Arc,
+    sync::{Arc, Mutex},
 };
 use tower_http::{
     cors::{Any, CorsLayer},
     services::ServeDir,
     trace::TraceLayer,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
 #[derive(Clone)]
 struct AppState {
     db: PgPool,
+    /// Shared so outgoing requests reuse pooled connections and TLS sessions.
+    http: reqwest::Client,
+    /// For downloading import images from URLs in the uploaded CSV: public addresses only.
+    import_http: reqwest::Client,
     storage_dir: PathBuf,
     anthropic_key: String,
     anthropic_model: String,
+    /// What turns images into classifications, picked by `CLASSIFIER`.
+    classifier: Arc<dyn Classifier>,
+    model_allowlist: Vec<String>,
+    default_tags: Vec<String>,
+    extra_fields: Vec<ExtraField>,
+    prompt_template: PromptTemplate,
+    /// `ENTRY_TITLE_TEMPLATE`, rendered into each summary's `display_title`.
+    title_template: Template<TitleField>,
+    /// Values for the template's `{tag_min}`, `{tag_max}` and `{category_list}`.
+    prompt_tag_range: (usize, usize),
+    prompt_categories: String,
+    collapse_plural_tags: bool,
+    /// Default for the per-request `generate_tags`.
+    generate_tags: bool,
+    few_shot_examples: Vec<FewShotExample>,
+    tag_synonyms: HashMap<String, Vec<String>>,
+    tag_translations: HashMap<String, HashMap<String, String>>,
+    category_rules: Option<HashMap<String, Vec<String>>>,
+    description_max_sentences: Option<usize>,
+    description_max_chars: Option<usize>,
+    public_base_url: Option<String>,
+    thumb_max_dim: u32,
+    thumb_format: ImageFormat,
+    reencode_format: Option<ImageFormat>,
+    strip_exif: bool,
+    webhook: Option<WebhookConfig>,
+    moderation: Option<ModerationConfig>,
+    write_token: Option<String>,
+    create_entry_timeout: std::time::Duration,
+    anthropic_max_retries: u32,
+    anthropic_backoff_ms: u64,
+    batch_max_images: usize,
+    batch_images_per_call: usize,
+    default_page_size: usize,
+    max_page_size: usize,
+    choose_label_strict: bool,
+    cleanup_batch_size: Option<usize>,
+    cleanup_hours: Option<HourWindow>,
+    /// How long a soft-deleted entry can be restored before the cleanup may purge it.
+    restore_window: Duration,
+    missing_confidence: MissingConfidence,
+    anthropic_image_quality: u8,
+    image_max_dimension: u32,
+    image_max_decode_bytes: u64,
+    allow_reset: bool,
+    store_raw_json: bool,
+    raw_json_retention_days: Option<usize>,
+    jobs: Arc<Mutex<HashMap<Uuid, JobStatus>>>,
+    cleanup_status: Arc<Mutex<CleanupStatus>>,
+    upload_locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+#[derive(Clone)]
+struct WebhookConfig {
+    url: String,
+    events: Vec<String>,
+}
+
+/// What to do when the model leaves out `confidence` (`MISSING_CONFIDENCE`).
+#[derive(Clone, Copy)]
+enum MissingConfidence {
+    /// Store `null`.
+    Null,
+    /// Store this value instead.
+    Default(f64),
+    /// Ask the model once more, for the confidence alone.
+    Retry,
+}
+
+/// What `/media` does when a requested file is missing on disk (`MEDIA_MISSING`).
+#[derive(Clone, Copy)]
+enum MediaMissing {
+    /// Plain 404, as served by `ServeDir`.
+    NotFound,
+    /// A 404 tagged with `X-Media-Missing` so clients can substitute their own image.
+    Header,
+    /// The bundled placeholder image.
+    Placeholder,
 }
 
+const PLACEHOLDER_SVG: &[u8] = include_bytes!("../assets/placeholder.svg");
+
+/// Bundled tag translations (`{ lang: { tag: label } }`); `TAG_TRANSLATIONS_FILE` adds to them.
+const TAG_TRANSLATIONS: &str = include_str!("../assets/tag_translations.json");
+
+/// Limits for every outgoing HTTP request (Anthropic, webhooks, imports).
+const HTTP_TIMEOUT_SECS: u64 = 90;
+const HTTP_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Events a webhook can subscribe to via `WEBHOOK_EVENTS`.
+const WEBHOOK_EVENTS: &[&str] = &["entry.deleted", "entry.purged"];
+
+/// Columns selected whenever an entry row is turned into a summary or detail.
+const ENTRY_COLUMNS: &str =
+    "id, created_at, image_path, thumb_path, label, description, confidence, tags, share_token, model, lang, extra, needs_review, is_pinned, reasoning, alternatives, edited_at, source_entry_id, source, lat, lng, classification_status, camera_make, camera_model, verified, verified_by, input_tokens, output_tokens";
+
+/// Formats we can encode with the `image` features enabled in Cargo.toml.
+const ENCODE_FORMATS: &[(&str, ImageFormat)] = &[
+    ("jpeg", ImageFormat::Jpeg),
+    ("jpg", ImageFormat::Jpeg),
+    ("png", ImageFormat::Png),
+    ("webp", ImageFormat::WebP),
+];
+
 #[derive(Serialize)]
 struct HealthResponse {
     status: &'static str,
@@ -42,11 +163,19 @@ struct EntrySummary {
     id: Uuid,
     created_at: DateTime<Utc>,
     image_url: String,
+    thumb_url: Option<String>,
     label: String,
     description: String,
     confidence: Option<f64>,
     tags: Vec<String>,
     shared: bool,
+    is_pinned: bool,
+    lat: Option<f64>,
+    lng: Option<f64>,
+    verified: bool,
+    model: Option<String>,
+    /// The label as shaped by `ENTRY_TITLE_TEMPLATE`; just the label by default.
+    display_title: String,
 }
 
 #[derive(Serialize)]
@@ -60,29 +189,623 @@ struct EntryDetail {
     tags: Vec<String>,
     shared: bool,
     share_url: Option<String>,
+    /// The model behind the current classification, updated by `reclassify`; `null` for
+    /// entries from before it was recorded, imports and failed classifications.
+    model: Option<String>,
+    lang: Option<String>,
+    extra: Option<serde_json::Value>,
+    needs_review: bool,
+    is_pinned: bool,
+    reasoning: Option<String>,
+    alternatives: serde_json::Value,
+    /// When a user last changed the model's classification.
+    edited_at: Option<DateTime<Utc>>,
+    /// The entry this one was cropped from via `classify-region`.
+    source_entry_id: Option<Uuid>,
+    /// Where the entry came from: `upload`, or the service it was imported from.
+    source: String,
+    /// From the upload's EXIF GPS tags or the imported observation; `null` when unknown.
+    lat: Option<f64>,
+    lng: Option<f64>,
+    /// `classified`, or `failed` for batch images stored with `on_failure=store`.
+    classification_status: String,
+    /// Camera make and model from the upload's EXIF.
+    camera_make: Option<String>,
+    camera_model: Option<String>,
+    /// Confirmed by a reviewer via `POST /api/entries/:id/verify`.
+    verified: bool,
+    verified_by: Option<String>,
+    /// Anthropic tokens spent classifying the entry, reclassifies included; `null` for
+    /// entries from before usage was recorded.
+    input_tokens: Option<i32>,
+    output_tokens: Option<i32>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Deserialize)]
+struct ListParams {
+    /// Truncates each summary's tags to the first N; detail endpoints always return all.
+    max_tags: Option<usize>,
+    /// Only entries carrying this tag.
+    tag: Option<String>,
+    /// Comma-separated; only entries carrying every one of these tags.
+    tags: Option<String>,
+    /// Words to look for in the label and description.
+    q: Option<String>,
+    /// Only entries flagged (or not flagged) by the category check.
+    needs_review: Option<bool>,
+    /// Only entries confirmed (or not yet confirmed) by a reviewer.
+    verified: Option<bool>,
+    /// Only entries shot with this device, as named by `/api/entries/by-device`.
+    device: Option<String>,
+    /// Stream every matching entry as one JSON array instead of returning a page.
+    #[serde(default)]
+    stream: bool,
+    /// `newest` (default; pinned first) or `random`.
+    order: Option<String>,
+    /// Seed for `order=random`; defaults to today's UTC date.
+    seed: Option<String>,
+    /// Set by the public endpoints: only entries with a share link.
+    #[serde(skip)]
+    shared_only: bool,
+}
+
+/// Longest `seed` accepted for `order=random`.
+const MAX_RANDOM_SEED_LEN: usize = 64;
+
+/// Result order of a list request.
+enum ListOrder {
+    /// Pinned first, then newest first; the order cursors are keyed on.
+    Newest,
+    /// Shuffled by hashing each id with the seed, so the same seed always yields the same
+    /// order and paging with `offset` stays consistent.
+    Random { seed: String },
+}
+
+impl ListOrder {
+    fn from_params(params: &ListParams) -> Result<Self, AppError> {
+        match params.order.as_deref().map(str::trim) {
+            None | Some("") | Some("newest") => Ok(ListOrder::Newest),
+            Some("random") => {
+                let seed = match params.seed.as_deref().map(str::trim) {
+                    Some(seed) if seed.len() > MAX_RANDOM_SEED_LEN => {
+                        return Err(AppError::bad_request(format!(
+                            "seed must be at most {} characters",
+                            MAX_RANDOM_SEED_LEN
+                        )));
+                    }
+                    Some(seed) if !seed.is_empty() => seed.to_string(),
+                    _ => Utc::now().format("%Y-%m-%d").to_string(),
+                };
+                Ok(ListOrder::Random { seed })
+            }
+            Some(other) => Err(AppError::bad_request(format!(
+                "Unknown order '{}': expected newest or random",
+                other
+            ))),
+        }
+    }
+
+    fn push(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            ListOrder::Newest => {
+                query.push(" ORDER BY is_pinned DESC, created_at DESC, id DESC");
+            }
+            ListOrder::Random { seed } => {
+                query
+                    .push(" ORDER BY md5(id::text || ")
+                    .push_bind(seed.clone())
+                    .push("), id");
+            }
+        }
+    }
+}
+
+/// `?limit=&offset=` (or `?after=<cursor>`) on list endpoints; resolved against
+/// `DEFAULT_PAGE_SIZE` / `MAX_PAGE_SIZE` by `Pagination::from_params`.
+#[derive(Deserialize)]
+struct PageParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    /// The previous page's `X-Next-Cursor`; unlike `offset`, stable while entries are added.
+    after: Option<String>,
+}
+
+/// A validated page window shared by every list endpoint.
+#[derive(Clone, Copy)]
+struct Pagination {
+    limit: usize,
+    offset: usize,
+    after: Option<ListCursor>,
+}
+
+/// Position of the last entry on a page, in list order (pinned first, then newest first).
+#[derive(Clone, Copy)]
+struct ListCursor {
+    is_pinned: bool,
+    created_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl Pagination {
+    /// Clamps `limit` to `max_page_size`; a missing limit means `default_page_size`.
+    fn from_params(state: &AppState, params: &PageParams) -> Result<Self, AppError> {
+        let limit = Self::limit(state, params.limit)?;
+        let offset = match params.offset {
+            Some(offset) if offset < 0 => {
+                return Err(AppError::bad_request("offset must not be negative"));
+            }
+            Some(offset) => offset as usize,
+            None => 0,
+        };
+        let after = match params.after.as_deref() {
+            Some(_) if params.offset.is_some() => {
+                return Err(AppError::bad_request("Use either after or offset, not both"));
+            }
+            Some(cursor) => Some(decode_cursor(cursor)?),
+            None => None,
+        };
+        Ok(Self {
+            limit,
+            offset,
+            after,
+        })
+    }
+
+    /// Just the `limit` half, for endpoints that return a single top-N list.
+    fn limit(state: &AppState, limit: Option<i64>) -> Result<usize, AppError> {
+        match limit {
+            Some(limit) if limit < 1 => Err(AppError::bad_request("limit must be at least 1")),
+            Some(limit) => Ok((limit as u64).min(state.max_page_size as u64) as usize),
+            None => Ok(state.default_page_size),
+        }
+    }
+
+    /// Adds the cursor condition; call while still building the `WHERE` clause.
+    fn push_after(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        if let Some(after) = self.after {
+            query
+                .push(" AND (is_pinned, created_at, id) < (")
+                .push_bind(after.is_pinned)
+                .push(", ")
+                .push_bind(after.created_at)
+                .push(", ")
+                .push_bind(after.id)
+                .push(")");
+        }
+    }
+
+    fn push(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        query
+            .push(" LIMIT ")
+            .push_bind(self.limit as i64)
+            .push(" OFFSET ")
+            .push_bind(self.offset as i64);
+    }
+}
+
+/// Opaque `after` cursor: URL-safe base64 of `pinned:created_at_micros:id`.
+fn encode_cursor(cursor: ListCursor) -> String {
+    let raw = format!(
+        "{}:{}:{}",
+        cursor.is_pinned as u8,
+        cursor.created_at.timestamp_micros(),
+        cursor.id
+    );
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(raw)
+}
+
+fn decode_cursor(cursor: &str) -> Result<ListCursor, AppError> {
+    let invalid = || AppError::bad_request("Invalid cursor");
+    let raw = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor.trim())
+        .map_err(|_| invalid())?;
+    let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+    let mut parts = raw.splitn(3, ':');
+    let is_pinned = match parts.next() {
+        Some("1") => true,
+        Some("0") => false,
+        _ => return Err(invalid()),
+    };
+    let created_at = parts
+        .next()
+        .and_then(|micros| micros.parse::<i64>().ok())
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(invalid)?;
+    let id = parts
+        .next()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(invalid)?;
+    Ok(ListCursor {
+        is_pinned,
+        created_at,
+        id,
+    })
+}
+
+#[derive(Deserialize)]
+struct TagsParams {
+    lang: Option<String>,
+    /// Only the N most used tags.
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct TagCount {
+    /// Canonical (English) tag; use this for `?tag=` filters.
+    tag: String,
+    /// Display label in the requested `lang`, or the canonical tag if untranslated.
+    label: String,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct DeviceCount {
+    /// Use this for `?device=` filters; `unknown` for entries without camera EXIF.
+    device: String,
+    make: Option<String>,
+    model: Option<String>,
+    count: i64,
+}
+
+#[derive(Serialize)]
+struct TagColor {
+    tag: String,
+    /// `#rrggbb`.
+    color: String,
+    /// False when `color` is the hash-based default.
+    assigned: bool,
+}
+
+#[derive(Deserialize)]
+struct TagColorsUpdate {
+    /// Tag to `#rgb`/`#rrggbb`, or `null` to go back to the default color.
+    colors: HashMap<String, Option<String>>,
+}
+
+#[derive(Deserialize)]
+struct ChangesParams {
+    since: Option<String>,
+}
+
+/// Fields of an existing entry that can change after creation.
+#[derive(Serialize)]
+struct EntryChange {
+    id: Uuid,
+    updated_at: DateTime<Utc>,
+    label: String,
+    description: String,
+    confidence: Option<f64>,
+    tags: Vec<String>,
+    shared: bool,
+}
+
+#[derive(Serialize)]
+struct ChangesResponse {
+    created: Vec<EntrySummary>,
+    updated: Vec<EntryChange>,
+    deleted: Vec<Uuid>,
+    cursor: String,
+}
+
+#[derive(Serialize)]
 struct SettingsPayload {
     is_public: bool,
+    /// Gives every new entry a share token as it is created.
+    auto_share: bool,
+    /// Shown on the public page while it has no entries; `None` shows nothing.
+    empty_message: Option<String>,
+}
+
+/// Settings changes; omitted fields keep their current value.
+#[derive(Deserialize)]
+struct SettingsUpdate {
+    is_public: Option<bool>,
+    auto_share: Option<bool>,
+    /// An empty string clears the message.
+    empty_message: Option<String>,
 }
 
+/// Longest accepted `empty_message`, in characters.
+const MAX_EMPTY_MESSAGE_CHARS: usize = 280;
+
 #[derive(Serialize, Deserialize)]
 struct SharePayload {
     enable: bool,
 }
 
+#[derive(Deserialize)]
+struct PinPayload {
+    enable: bool,
+}
+
+/// `PATCH /api/entries/:id`; omitted fields are left unchanged.
+#[derive(Deserialize)]
+struct EntryPatch {
+    label: Option<String>,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct PatchParams {
+    /// Regenerate the tags from the (edited) label and description; one text-only model call.
+    #[serde(default)]
+    retag: bool,
+}
+
+#[derive(Deserialize)]
+struct VerifyPayload {
+    verified: bool,
+    /// Who confirmed the identification; required when verifying.
+    verified_by: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FeedbackPayload {
+    correct: bool,
+    corrected_label: Option<String>,
+}
+
+/// A pixel box within the stored image, plus the usual upload options.
+#[derive(Deserialize)]
+struct ClassifyRegionPayload {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    model: Option<String>,
+    lang: Option<String>,
+    #[serde(default)]
+    explain: bool,
+    hint: Option<String>,
+    generate_tags: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ChooseLabelPayload {
+    label: String,
+    /// Accept a label that isn't among the stored alternatives; refused under `CHOOSE_LABEL_STRICT`.
+    #[serde(default)]
+    freeform: bool,
+}
+
+#[derive(Serialize)]
+struct FeedbackResponse {
+    id: Uuid,
+    entry_id: Uuid,
+    model: Option<String>,
+    label: String,
+    correct: bool,
+    corrected_label: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ModelAccuracy {
+    model: String,
+    correct: i64,
+    incorrect: i64,
+    accuracy: f64,
+}
+
+#[derive(Serialize)]
+struct ModelUsage {
+    /// `null` for entries created before the model was recorded.
+    model: Option<String>,
+    entries: i64,
+    first_created_at: DateTime<Utc>,
+    last_created_at: DateTime<Utc>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct Classification {
     label: String,
     description: String,
+    /// Missing when tags weren't asked for.
+    #[serde(default)]
     tags: Vec<String>,
     confidence: Option<f64>,
+    /// Values for the deployment's `CLASSIFICATION_EXTRA_FIELDS`.
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    extra: serde_json::Map<String, serde_json::Value>,
+    /// Why the model chose this label; only requested in explain mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reasoning: Option<String>,
+    /// Runner-up identifications; filled leniently by `collect_alternatives`.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Vec::is_empty")]
+    alternatives: Vec<Alternative>,
+    /// Tokens the classification cost; not part of the model's answer.
+    #[serde(skip)]
+    usage: Option<TokenUsage>,
+}
+
+/// The `usage` Anthropic reports with each message.
+#[derive(Clone, Copy)]
+struct TokenUsage {
+    input_tokens: i32,
+    output_tokens: i32,
+}
+
+impl TokenUsage {
+    fn from_response(response: &serde_json::Value) -> Option<Self> {
+        let usage = response.get("usage")?;
+        let count = |field: &str| usage.get(field).and_then(|v| v.as_i64()).map(|n| n as i32);
+        Some(Self {
+            input_tokens: count("input_tokens")?,
+            output_tokens: count("output_tokens")?,
+        })
+    }
+
+    /// An equal share of a call that covered `parts` results.
+    fn split(self, parts: usize) -> Self {
+        let parts = parts.max(1) as i32;
+        Self {
+            input_tokens: self.input_tokens / parts,
+            output_tokens: self.output_tokens / parts,
+        }
+    }
+}
+
+impl Classification {
+    /// Placeholder for an image that couldn't be classified.
+    fn unclassified() -> Self {
+        Self {
+            label: "Unclassified".to_string(),
+            description: String::new(),
+            tags: Vec::new(),
+            confidence: None,
+            extra: serde_json::Map::new(),
+            reasoning: None,
+            alternatives: Vec::new(),
+            usage: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Alternative {
+    label: String,
+    confidence: Option<f64>,
+}
+
+/// An operator-defined field requested from the model alongside the built-in ones.
+#[derive(Clone)]
+struct ExtraField {
+    name: String,
+    description: String,
+}
+
+#[derive(Deserialize, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Json,
+    Zip,
+}
+
+#[derive(Deserialize)]
+struct BatchGetPayload {
+    ids: Vec<Uuid>,
+}
+
+#[derive(Serialize)]
+struct BatchGetResponse {
+    /// Found entries, in request order.
+    entries: Vec<EntryDetail>,
+    /// Requested ids that don't exist or were deleted.
+    missing: Vec<Uuid>,
+}
+
+#[derive(Deserialize)]
+struct ExportSelectedPayload {
+    ids: Vec<Uuid>,
+    #[serde(default)]
+    format: ExportFormat,
+    /// Bundle the original image files (zip only, defaults to true).
+    include_images: Option<bool>,
+}
+
+/// Self-contained record of one entry, used by every export format.
+#[derive(Serialize)]
+struct ExportEntry {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+    label: String,
+    description: String,
+    confidence: Option<f64>,
+    tags: Vec<String>,
+    image_url: String,
+    image_file: String,
+    image_mime: String,
+    image_width: Option<i32>,
+    image_height: Option<i32>,
+    model: Option<String>,
+    lang: Option<String>,
+    extra: Option<serde_json::Value>,
+    reasoning: Option<String>,
+    alternatives: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ExportDocument {
+    exported_at: DateTime<Utc>,
+    entries: Vec<ExportEntry>,
+    /// Requested ids that don't exist or were deleted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing: Vec<Uuid>,
+}
+
+/// A single entry exported on its own by `export_entry`.
+#[derive(Serialize)]
+struct EntryExportDocument {
+    exported_at: DateTime<Utc>,
+    entry: ExportEntry,
+}
+
+/// Moderation pre-check, enabled by setting `MODERATION_PROMPT`.
+#[derive(Clone)]
+struct ModerationConfig {
+    prompt: String,
+    threshold: f64,
+    max_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct ModerationVerdict {
+    allowed: bool,
+    score: Option<f64>,
+    reason: Option<String>,
+}
+
+/// Per-request knobs for a single classification call.
+#[derive(Clone)]
+struct ClassifyOptions {
+    model: String,
+    /// Language for `label`/`description`; tags always stay in English.
+    lang: Option<String>,
+    /// Also ask for the model's `reasoning`; opt-in because it costs extra tokens.
+    explain: bool,
+    /// Free-text hint from the uploader, e.g. where the photo was taken.
+    hint: Option<String>,
+    /// Off skips tags in the prompt and stores none, not even `DEFAULT_TAGS`.
+    generate_tags: bool,
 }
 
 #[derive(Serialize)]
 struct CreateEntryResponse {
     entry: EntryDetail,
+    /// The upload matched an existing entry's image, which is returned instead of a new one.
+    duplicate: bool,
+}
+
+#[derive(Deserialize)]
+struct CreateEntryParams {
+    /// Classify and store the upload even if an entry already has the same image.
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Clone, Serialize)]
+struct JobStatus {
+    id: Uuid,
+    kind: &'static str,
+    state: JobState,
+    total: usize,
+    processed: usize,
+    failed: usize,
+    started_at: DateTime<Utc>,
+    finished_at: Option<DateTime<Utc>>,
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    findings: Vec<serde_json::Value>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JobState {
+    Running,
+    Completed,
+    Failed,
 }
 
 #[tokio::main]
@@ -94,393 +817,6148 @@ async fn main() -> anyhow::Result<()> {
 
     let database_url = std::env::var("DATABASE_URL")
         .expect("DATABASE_URL must be set");
-    let anthropic_key = std::env::var("ANTHROPIC_API_KEY")
-        .expect("ANTHROPIC_API_KEY must be set");
+    let classifier: Arc<dyn Classifier> = match std::env::var("CLASSIFIER").as_deref().map(str::trim) {
+        Ok("anthropic") | Ok("") | Err(_) => Arc::new(AnthropicClassifier),
+        Ok("mock") => Arc::new(MockClassifier),
+        Ok("openai") => Arc::new(OpenAiClassifier {
+            api_key: std::env::var("OPENAI_API_KEY")
+                .map_err(|_| anyhow::anyhow!("OPENAI_API_KEY must be set with CLASSIFIER=openai"))?,
+            model: std::env::var("OPENAI_MODEL")
+                .ok()
+                .map(|model| model.trim().to_string())
+                .filter(|model| !model.is_empty())
+                .unwrap_or_else(|| "gpt-4o".to_string()),
+        }),
+        Ok(other) => anyhow::bail!("Invalid CLASSIFIER '{}': use anthropic, openai or mock", other),
+    };
+    let anthropic_key = match classifier.needs_api_key() {
+        true => std::env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY must be set"),
+        false => std::env::var("ANTHROPIC_API_KEY").unwrap_or_default(),
+    };
     let anthropic_model = std::env::var("ANTHROPIC_MODEL")
         .unwrap_or_else(|_| "claude-opus-4-5".to_string());
     let storage_dir = PathBuf::from(
         std::env::var("STORAGE_DIR").unwrap_or_else(|_| "storage".to_string()),
     );
+    let model_allowlist: Vec<String> = std::env::var("ANTHROPIC_MODEL_ALLOWLIST")
+        .unwrap_or_default()
+        .split(',')
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .collect();
+    let default_tags: Vec<String> = std::env::var("DEFAULT_TAGS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let extra_fields =
+        parse_extra_fields(&std::env::var("CLASSIFICATION_EXTRA_FIELDS").unwrap_or_default())?;
+    let tag_synonyms = parse_tag_synonyms(&std::env::var("TAG_SYNONYMS").unwrap_or_default());
+    let tag_translations = load_tag_translations()?;
+    let category_rules = match std::env::var("CATEGORY_VALIDATION").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") => {
+            let rules = std::env::var("CATEGORY_RULES")
+                .unwrap_or_else(|_| DEFAULT_CATEGORY_RULES.to_string());
+            Some(parse_category_rules(&rules)?)
+        }
+        Ok("false") | Ok("0") | Ok("") | Err(_) => None,
+        Ok(other) => anyhow::bail!("Invalid CATEGORY_VALIDATION '{}': use true or false", other),
+    };
+    let prompt_template = match std::env::var("CLASSIFICATION_PROMPT_TEMPLATE") {
+        Ok(template) if !template.trim().is_empty() => PromptTemplate::parse(template.trim())
+            .map_err(|err| anyhow::anyhow!("Invalid CLASSIFICATION_PROMPT_TEMPLATE: {}", err))?,
+        _ => PromptTemplate::parse(DEFAULT_CLASSIFICATION_PROMPT).map_err(anyhow::Error::msg)?,
+    };
+    let title_template = match std::env::var("ENTRY_TITLE_TEMPLATE") {
+        Ok(template) if !template.trim().is_empty() => Template::parse(template.trim())
+            .map_err(|err| anyhow::anyhow!("Invalid ENTRY_TITLE_TEMPLATE: {}", err))?,
+        _ => Template::parse("{label}").map_err(anyhow::Error::msg)?,
+    };
+    let prompt_tag_range = (env_usize("TAG_MIN", 3)?, env_usize("TAG_MAX", 6)?);
+    if prompt_tag_range.0 > prompt_tag_range.1 {
+        anyhow::bail!("TAG_MIN must not be greater than TAG_MAX");
+    }
+    // Listed from CATEGORY_RULES even when CATEGORY_VALIDATION is off, so a template can
+    // steer the model towards the categories without entries being checked against them.
+    let prompt_categories = {
+        let rules = match &category_rules {
+            Some(rules) => rules.clone(),
+            None => parse_category_rules(
+                &std::env::var("CATEGORY_RULES").unwrap_or_else(|_| DEFAULT_CATEGORY_RULES.to_string()),
+            )?,
+        };
+        let mut categories: Vec<String> = rules.into_keys().collect();
+        categories.sort();
+        categories.join(", ")
+    };
+    let collapse_plural_tags = match std::env::var("TAG_COLLAPSE_PLURALS").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") | Ok("") | Err(_) => false,
+        Ok(other) => anyhow::bail!("Invalid TAG_COLLAPSE_PLURALS '{}': use true or false", other),
+    };
+    let generate_tags = match std::env::var("GENERATE_TAGS").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") | Ok("") | Err(_) => true,
+        Ok("false") | Ok("0") => false,
+        Ok(other) => anyhow::bail!("Invalid GENERATE_TAGS '{}': use true or false", other),
+    };
+    if category_rules.is_some() && !extra_fields.iter().any(|field| field.name == "category") {
+        warn!("CATEGORY_VALIDATION is on but CLASSIFICATION_EXTRA_FIELDS has no 'category' field; nothing will be checked");
+    }
+    let description_max_sentences = env_optional_usize("DESCRIPTION_MAX_SENTENCES")?;
+    let description_max_chars = env_optional_usize("DESCRIPTION_MAX_CHARS")?;
+    let public_base_url = std::env::var("PUBLIC_BASE_URL")
+        .ok()
+        .map(|v| v.trim().trim_end_matches('/').to_string())
+        .filter(|v| !v.is_empty());
+    let thumb_max_dim = u32::try_from(env_usize("THUMB_MAX_DIM", 320)?)?;
+    let thumb_format = match std::env::var("THUMB_FORMAT") {
+        Ok(value) => parse_image_format("THUMB_FORMAT", &value, "jpeg")?,
+        Err(_) => ImageFormat::Jpeg,
+    };
+    let reencode_format = match std::env::var("IMAGE_REENCODE_FORMAT") {
+        Ok(value) if !value.trim().is_empty() => {
+            Some(parse_image_format("IMAGE_REENCODE_FORMAT", &value, "unset")?)
+        }
+        _ => None,
+    };
+    let strip_exif = match std::env::var("STRIP_EXIF").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") | Ok("") | Err(_) => true,
+        Ok("false") | Ok("0") => false,
+        Ok(other) => anyhow::bail!("Invalid STRIP_EXIF '{}': use true or false", other),
+    };
+    let webhook = match std::env::var("WEBHOOK_URL") {
+        Ok(url) if !url.trim().is_empty() => {
+            let events: Vec<String> = match std::env::var("WEBHOOK_EVENTS") {
+                Ok(value) => value
+                    .split(',')
+                    .map(|event| event.trim().to_string())
+                    .filter(|event| !event.is_empty())
+                    .collect(),
+                Err(_) => WEBHOOK_EVENTS.iter().map(|event| event.to_string()).collect(),
+            };
+            if let Some(unknown) = events
+                .iter()
+                .find(|event| !WEBHOOK_EVENTS.contains(&event.as_str()))
+            {
+                anyhow::bail!(
+                    "Unknown WEBHOOK_EVENTS entry '{}': expected one of {}",
+                    unknown,
+                    WEBHOOK_EVENTS.join(", ")
+                );
+            }
+            Some(WebhookConfig {
+                url: url.trim().to_string(),
+                events,
+            })
+        }
+        _ => None,
+    };
+    let missing_confidence = match std::env::var("MISSING_CONFIDENCE").as_deref().map(str::trim) {
+        Ok("") | Ok("null") | Err(_) => MissingConfidence::Null,
+        Ok("retry") => MissingConfidence::Retry,
+        Ok(other) => match other.parse::<f64>() {
+            Ok(value) if (0.0..=1.0).contains(&value) => MissingConfidence::Default(value),
+            _ => anyhow::bail!(
+                "Unsupported MISSING_CONFIDENCE '{}': expected null, retry or a number between 0 and 1 (default: null)",
+                other
+            ),
+        },
+    };
+    let anthropic_image_quality = match env_usize("ANTHROPIC_IMAGE_QUALITY", 85)? {
+        quality @ 1..=100 => quality as u8,
+        _ => anyhow::bail!("ANTHROPIC_IMAGE_QUALITY must be between 1 and 100 (default: 85)"),
+    };
+    let few_shot_examples = match std::env::var("FEW_SHOT_EXAMPLES") {
+        Ok(value) if !value.trim().is_empty() => {
+            load_few_shot_examples(&storage_dir, &value, anthropic_image_quality)?
+        }
+        _ => Vec::new(),
+    };
+    let image_max_dimension = u32::try_from(env_usize("IMAGE_MAX_DIMENSION", 12_000)?)
+        .map_err(|_| anyhow::anyhow!("IMAGE_MAX_DIMENSION is too large"))?;
+    let image_max_decode_bytes = env_usize("IMAGE_MAX_DECODE_MB", 512)? as u64 * 1024 * 1024;
+    let allow_reset = match std::env::var("ADMIN_ALLOW_RESET").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") | Ok("") | Err(_) => false,
+        Ok(other) => anyhow::bail!("Invalid ADMIN_ALLOW_RESET '{}': use true or false", other),
+    };
+    let store_raw_json = match std::env::var("STORE_RAW_JSON").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") | Ok("") | Err(_) => true,
+        Ok("false") | Ok("0") => false,
+        Ok(other) => anyhow::bail!("Invalid STORE_RAW_JSON '{}': use true or false", other),
+    };
+    let raw_json_retention_days = env_optional_usize("RAW_JSON_RETENTION_DAYS")?;
+    let media_missing = match std::env::var("MEDIA_MISSING").as_deref().map(str::trim) {
+        Ok("placeholder") => MediaMissing::Placeholder,
+        Ok("header") => MediaMissing::Header,
+        Ok("") | Ok("404") | Err(_) => MediaMissing::NotFound,
+        Ok(other) => anyhow::bail!(
+            "Unsupported MEDIA_MISSING '{}': expected placeholder, header or 404 (default: 404)",
+            other
+        ),
+    };
+    let json_body_limit = env_usize("JSON_BODY_LIMIT_KB", 64)? * 1024;
+    let upload_body_limit = env_usize("UPLOAD_BODY_LIMIT_MB", 10)? * 1024 * 1024;
+    let max_page_size = env_usize("MAX_PAGE_SIZE", 200)?;
+    let default_page_size = env_usize("DEFAULT_PAGE_SIZE", 50)?.min(max_page_size);
+    let cleanup_batch_size = env_optional_usize("CLEANUP_BATCH_SIZE")?;
+    let restore_window = Duration::minutes(env_usize("RESTORE_WINDOW_MINUTES", 60)? as i64);
+    let cleanup_hours = match std::env::var("CLEANUP_HOURS") {
+        Ok(value) if !value.trim().is_empty() => Some(parse_hour_window("CLEANUP_HOURS", &value)?),
+        _ => None,
+    };
+    let choose_label_strict = match std::env::var("CHOOSE_LABEL_STRICT").as_deref().map(str::trim) {
+        Ok("true") | Ok("1") => true,
+        Ok("false") | Ok("0") | Ok("") | Err(_) => false,
+        Ok(other) => anyhow::bail!("Invalid CHOOSE_LABEL_STRICT '{}': use true or false", other),
+    };
+    let create_entry_timeout =
+        std::time::Duration::from_secs(env_usize("CREATE_ENTRY_TIMEOUT_SECS", 120)? as u64);
+    let anthropic_max_retries = match std::env::var("ANTHROPIC_MAX_RETRIES") {
+        Ok(value) if !value.trim().is_empty() => value.trim().parse::<u32>().map_err(|_| {
+            anyhow::anyhow!("ANTHROPIC_MAX_RETRIES must be a non-negative integer (default: 3)")
+        })?,
+        _ => 3,
+    };
+    let anthropic_backoff_ms = env_usize("ANTHROPIC_BACKOFF_MS", 500)? as u64;
+    let batch_max_images = env_usize("BATCH_MAX_IMAGES", 20)?;
+    let batch_images_per_call = env_usize("BATCH_IMAGES_PER_CALL", 4)?;
+    let moderation = match std::env::var("MODERATION_PROMPT") {
+        Ok(prompt) if !prompt.trim().is_empty() => {
+            let threshold = match std::env::var("MODERATION_THRESHOLD") {
+                Ok(value) => value
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|threshold| (0.0..=1.0).contains(threshold))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("MODERATION_THRESHOLD must be between 0 and 1 (default: 0.5)")
+                    })?,
+                Err(_) => 0.5,
+            };
+            Some(ModerationConfig {
+                prompt: prompt.trim().to_string(),
+                threshold,
+                max_tokens: env_usize("MODERATION_MAX_TOKENS", 128)?,
+            })
+        }
+        _ => None,
+    };
+    let write_token = std::env::var("WRITE_TOKEN")
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty());
+    if write_token.is_none() {
+        warn!("WRITE_TOKEN is not set; admin routes are unauthenticated");
+    }
+    if allow_reset && write_token.is_none() {
+        anyhow::bail!("ADMIN_ALLOW_RESET requires WRITE_TOKEN to be set");
+    }
     let images_dir = storage_dir.join("images");
     std::fs::create_dir_all(&images_dir)?;
 
-    let db = PgPoolOptions::new()
-        .max_connections(10)
-        .connect(&database_url)
-        .await?;
-
-    sqlx::migrate!("./migrations").run(&db).await?;
+    let connect_attempts = env_usize("DB_CONNECT_ATTEMPTS", 10)?;
+    let connect_backoff_ms = env_usize("DB_CONNECT_BACKOFF_MS", 1000)? as u64;
+    let db = connect_and_migrate(&database_url, connect_attempts, connect_backoff_ms).await?;
     ensure_settings(&db).await?;
 
+    let http = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(HTTP_TIMEOUT_SECS))
+        .connect_timeout(std::time::Duration::from_secs(HTTP_CONNECT_TIMEOUT_SECS))
+        .build()?;
+    let import_http = import_client()?;
+
     let state = Arc::new(AppState {
         db,
+        http,
+        import_http,
         storage_dir,
         anthropic_key,
         anthropic_model,
+        classifier,
+        model_allowlist,
+        default_tags,
+        extra_fields,
+        prompt_template,
+        title_template,
+        prompt_tag_range,
+        prompt_categories,
+        collapse_plural_tags,
+        generate_tags,
+        few_shot_examples,
+        tag_synonyms,
+        tag_translations,
+        category_rules,
+        description_max_sentences,
+        description_max_chars,
+        public_base_url,
+        thumb_max_dim,
+        thumb_format,
+        reencode_format,
+        strip_exif,
+        webhook,
+        moderation,
+        write_token,
+        create_entry_timeout,
+        anthropic_max_retries,
+        anthropic_backoff_ms,
+        batch_max_images,
+        batch_images_per_call,
+        default_page_size,
+        max_page_size,
+        choose_label_strict,
+        cleanup_batch_size,
+        cleanup_hours,
+        restore_window,
+        missing_confidence,
+        anthropic_image_quality,
+        image_max_dimension,
+        image_max_decode_bytes,
+        allow_reset,
+        store_raw_json,
+        raw_json_retention_days,
+        jobs: Arc::new(Mutex::new(HashMap::new())),
+        cleanup_status: Arc::new(Mutex::new(CleanupStatus::default())),
+        upload_locks: Arc::new(Mutex::new(HashMap::new())),
     });
 
     spawn_cleanup(state.clone());
 
+    let app = build_router(state, media_missing, json_body_limit, upload_body_limit);
+
+    let addr: SocketAddr = "0.0.0.0:4000".parse()?;
+    info!("listening on {}", addr);
+    axum::serve(tokio::net::TcpListener::bind(addr).await?, app).await?;
+
+    Ok(())
+}
+
+/// All routes, on top of an already configured state.
+fn build_router(
+    state: Arc<AppState>,
+    media_missing: MediaMissing,
+    json_body_limit: usize,
+    upload_body_limit: usize,
+) -> Router {
+    let admin = Router::new()
+        .route("/thumbnails/backfill", post(backfill_thumbnails))
+        .route("/thumbnails/regenerate", post(regenerate_thumbnails))
+        .route("/entries/missing-thumbnails", get(list_missing_thumbnails))
+        .route("/reprocess-images", post(reprocess_images))
+        .route("/backfill-hashes", post(backfill_hashes))
+        .route("/reset", post(reset_collection))
+        .route("/cleanup/preview", get(preview_cleanup))
+        .route("/cleanup/status", get(get_cleanup_status))
+        .route("/jobs/:id", get(get_job))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_write_token));
+
+    let reviewer = Router::new()
+        .route("/entries/:id/verify", post(verify_entry))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_write_token));
+
     let api = Router::new()
         .route("/health", get(health))
         .route("/settings", get(get_settings).put(update_settings))
-        .route("/entries", get(list_entries).post(create_entry))
-        .route("/entries/:id", get(get_entry))
+        .route("/settings/validate", post(validate_settings_payload))
+        .route("/settings/export", get(export_settings))
+        .route("/settings/import", post(import_settings))
+        .route(
+            "/entries",
+            get(list_entries).post(create_entry.layer(DefaultBodyLimit::max(upload_body_limit))),
+        )
+        .route(
+            "/entries/batch",
+            post(create_entries_batch.layer(DefaultBodyLimit::max(upload_body_limit))),
+        )
+        .route("/entries/changes", get(list_entry_changes))
+        .route("/entries/recent", get(list_recent_entries))
+        .route("/entries.geojson", get(entries_geojson))
+        .route("/entries/by-device", get(list_devices))
+        .route("/entries/batch-get", post(batch_get_entries))
+        .route(
+            "/entries/similar-to",
+            post(similar_entries.layer(DefaultBodyLimit::max(upload_body_limit))),
+        )
+        .route("/entries/delete-by-tag", post(delete_entries_by_tag))
+        .route("/entries/bulk-delete", post(bulk_delete_entries))
+        .route("/entries/:id", get(get_entry).patch(patch_entry))
         .route("/entries/:id/delete", post(soft_delete_entry))
         .route("/entries/:id/restore", post(restore_entry))
+        .route("/trash", get(list_trash))
         .route("/entries/:id/share", post(toggle_share))
+        .route("/entries/:id/pin", post(toggle_pin))
+        .route("/entries/:id/choose-label", post(choose_label))
+        .route("/entries/:id/classify-region", post(classify_region))
+        .route("/entries/:id/reclassify", post(reclassify_entry))
+        .route("/entries/:id/export.json", get(export_entry))
+        .route("/entries/:id/feedback", post(submit_feedback))
         .route("/share/:token", get(get_shared_entry))
         .route("/public/entries", get(list_public_entries))
+        .route("/public/meta", get(public_meta))
+        .route("/public/entries/recent", get(list_public_recent_entries))
+        .route("/public/entries.geojson", get(public_entries_geojson))
+        .route("/tags", get(list_tags))
+        .route("/tags/cooccurrence", get(tag_cooccurrence))
+        .route("/tag-colors", get(get_tag_colors).put(update_tag_colors))
+        .route("/stats/accuracy", get(accuracy_stats))
+        .route("/stats/models", get(model_stats))
+        .route("/stats/tags", get(tag_stats))
+        .route("/usage", get(token_usage))
+        .route("/export/selected", post(export_selected))
+        .route("/export.pdf", get(export_pdf))
+        .route(
+            "/import/inaturalist",
+            post(import_inaturalist.layer(DefaultBodyLimit::max(upload_body_limit))),
+        )
+        .nest("/admin", admin)
+        .merge(reviewer)
+        .layer(DefaultBodyLimit::max(json_body_limit))
         .with_state(state.clone());
 
+    // ServeDir already answers `Range` requests with 206 + `Content-Range`, and
+    // advertises `Accept-Ranges: bytes`, so media needs no handler of its own.
+    let media = ServeDir::new(state.storage_dir.clone());
     let app = Router::new()
         .nest("/api", api)
-        .nest_service("/media", ServeDir::new(state.storage_dir.clone()))
-        .layer(TraceLayer::new_for_http())
+        .route("/share/:token/page", get(shared_entry_page))
+        .with_state(state.clone());
+    let app = match media_missing {
+        MediaMissing::NotFound => app.nest_service("/media", media),
+        MediaMissing::Header => {
+            app.nest_service("/media", media.fallback(missing_media_header.into_service()))
+        }
+        MediaMissing::Placeholder => app.nest_service(
+            "/media",
+            media.fallback(missing_media_placeholder.into_service()),
+        ),
+    };
+
+    app.layer(TraceLayer::new_for_http())
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
-                .allow_headers(Any),
+                .allow_headers(Any)
+                .expose_headers([
+                    header::HeaderName::from_static("x-total-count"),
+                    header::HeaderName::from_static("x-next-cursor"),
+                    header::HeaderName::from_static("x-random-seed"),
+                    header::RETRY_AFTER,
+                    header::ETAG,
+                ]),
         )
-        .layer(DefaultBodyLimit::max(10 * 1024 * 1024));
+}
 
-    let addr: SocketAddr = "0.0.0.0:4000".parse()?;
-    info!("listening on {}", addr);
-    axum::serve(tokio::net::TcpListener::bind(addr).await?, app).await?;
+/// Connects to Postgres and runs migrations, retrying while the database comes up.
+///
+/// The delay starts at `backoff_ms` and doubles after each failure, capped at 30s.
+async fn connect_and_migrate(
+    database_url: &str,
+    attempts: usize,
+    backoff_ms: u64,
+) -> anyhow::Result<PgPool> {
+    let mut delay = std::time::Duration::from_millis(backoff_ms);
+    for attempt in 1..=attempts {
+        let result = async {
+            let db = PgPoolOptions::new()
+                .max_connections(10)
+                .connect(database_url)
+                .await?;
+            sqlx::migrate!("./migrations").run(&db).await?;
+            anyhow::Ok(db)
+        }
+        .await;
 
-    Ok(())
+        match result {
+            Ok(db) => return Ok(db),
+            Err(err) if attempt < attempts => {
+                warn!(
+                    "database not ready (attempt {}/{}): {}; retrying in {:?}",
+                    attempt, attempts, err, delay
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(std::time::Duration::from_secs(30));
+            }
+            Err(err) => {
+                error!("database not ready after {} attempts: {}", attempts, err);
+                return Err(err);
+            }
+        }
+    }
+    unreachable!("DB_CONNECT_ATTEMPTS is at least 1")
 }
 
-async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "ok",
-        model: state.anthropic_model.clone(),
-    })
+/// Reads a positive integer from the environment, falling back to `default` when unset.
+fn env_usize(var: &str, default: usize) -> anyhow::Result<usize> {
+    Ok(env_optional_usize(var)?.unwrap_or(default))
+}
+
+/// Reads an optional positive integer from the environment; empty counts as unset.
+fn env_optional_usize(var: &str) -> anyhow::Result<Option<usize>> {
+    match std::env::var(var) {
+        Ok(value) if value.trim().is_empty() => Ok(None),
+        Ok(value) => value
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|parsed| *parsed > 0)
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("{} must be a positive integer", var)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Requires `Authorization: Bearer $WRITE_TOKEN` when a write token is configured.
+async fn require_write_token(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if let Some(expected) = &state.write_token {
+        let provided = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or("");
+        if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+            return Err(AppError::unauthorized("Missing or invalid write token"));
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn missing_media_header() -> impl IntoResponse {
+    (StatusCode::NOT_FOUND, [("x-media-missing", "true")])
+}
+
+async fn missing_media_placeholder() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "no-store"),
+            (header::HeaderName::from_static("x-media-missing"), "true"),
+        ],
+        PLACEHOLDER_SVG,
+    )
+}
+
+async fn health(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        model: classification_model(&state).to_string(),
+    })
 }
 
 async fn get_settings(State(state): State<Arc<AppState>>) -> Result<Json<SettingsPayload>, AppError> {
-    let row = sqlx::query("SELECT is_public FROM settings WHERE id = 1")
+    let row = sqlx::query("SELECT is_public, auto_share, empty_message FROM settings WHERE id = 1")
         .fetch_one(&state.db)
         .await?;
 
     Ok(Json(SettingsPayload {
         is_public: row.get("is_public"),
+        auto_share: row.get("auto_share"),
+        empty_message: row.get("empty_message"),
     }))
 }
 
+#[derive(Serialize)]
+struct SettingsValidation {
+    valid: bool,
+    /// Field name to what is wrong with it.
+    errors: BTreeMap<String, String>,
+}
+
+/// Checks a proposed settings change the same way `update_settings` does, without saving it.
+async fn validate_settings_payload(
+    Json(payload): Json<serde_json::Value>,
+) -> Json<SettingsValidation> {
+    let errors = validate_settings(&payload);
+    Json(SettingsValidation {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+/// Per-field problems with a settings change; empty when it can be applied.
+fn validate_settings(payload: &serde_json::Value) -> BTreeMap<String, String> {
+    let mut errors = BTreeMap::new();
+    let Some(fields) = payload.as_object() else {
+        errors.insert("_".to_string(), "Settings must be a JSON object".to_string());
+        return errors;
+    };
+    for (field, value) in fields {
+        let error = match field.as_str() {
+            "is_public" | "auto_share" => {
+                (!value.is_boolean() && !value.is_null()).then(|| "Must be true or false".to_string())
+            }
+            "empty_message" => match value {
+                serde_json::Value::Null => None,
+                serde_json::Value::String(message)
+                    if message.trim().chars().count() > MAX_EMPTY_MESSAGE_CHARS =>
+                {
+                    Some(format!("Must be at most {} characters", MAX_EMPTY_MESSAGE_CHARS))
+                }
+                serde_json::Value::String(_) => None,
+                _ => Some("Must be a string".to_string()),
+            },
+            _ => Some("Unknown setting".to_string()),
+        };
+        if let Some(error) = error {
+            errors.insert(field.clone(), error);
+        }
+    }
+    errors
+}
+
 async fn update_settings(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<SettingsPayload>,
+    Json(payload): Json<serde_json::Value>,
 ) -> Result<Json<SettingsPayload>, AppError> {
-    sqlx::query("UPDATE settings SET is_public = $1, updated_at = NOW() WHERE id = 1")
-        .bind(payload.is_public)
-        .execute(&state.db)
-        .await?;
-
-    Ok(Json(payload))
+    let errors = validate_settings(&payload);
+    save_settings(&state, payload, errors).await
 }
 
-async fn list_entries(State(state): State<Arc<AppState>>) -> Result<Json<Vec<EntrySummary>>, AppError> {
-    let rows = sqlx::query(
-        "SELECT id, created_at, image_path, label, description, confidence, tags, share_token \
-         FROM entries WHERE deleted_at IS NULL ORDER BY created_at DESC",
+/// The whole settings object as a downloadable file for `POST /api/settings/import`.
+async fn export_settings(State(state): State<Arc<AppState>>) -> Result<Response, AppError> {
+    let Json(settings) = get_settings(State(state)).await?;
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"naturadex-settings.json\"",
+        )],
+        Json(settings),
     )
-    .fetch_all(&state.db)
-    .await?;
-
-    let entries = rows.into_iter().map(entry_summary_from_row).collect();
-    Ok(Json(entries))
+        .into_response())
 }
 
-async fn list_public_entries(
+/// Replaces every setting with an exported object. Unlike `PUT /api/settings` nothing
+/// is left as it was: the booleans are required and a missing `empty_message` clears it.
+async fn import_settings(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<EntrySummary>>, AppError> {
-    let row = sqlx::query("SELECT is_public FROM settings WHERE id = 1")
-        .fetch_one(&state.db)
-        .await?;
-    let is_public: bool = row.get("is_public");
-
-    if !is_public {
-        return Err(AppError::not_found("Collection not public"));
+    Json(mut payload): Json<serde_json::Value>,
+) -> Result<Json<SettingsPayload>, AppError> {
+    let mut errors = validate_settings(&payload);
+    if let Some(fields) = payload.as_object_mut() {
+        for field in ["is_public", "auto_share"] {
+            if fields.get(field).is_none_or(|value| value.is_null()) {
+                errors.insert(field.to_string(), "Required on import".to_string());
+            }
+        }
+        if fields.get("empty_message").is_none_or(|value| value.is_null()) {
+            fields.insert("empty_message".to_string(), serde_json::Value::String(String::new()));
+        }
     }
-
-    list_entries(State(state)).await
+    save_settings(&state, payload, errors).await
 }
 
-async fn get_entry(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<EntryDetail>, AppError> {
+/// Applies a settings change once `errors` (from `validate_settings`) came back empty.
+async fn save_settings(
+    state: &AppState,
+    payload: serde_json::Value,
+    errors: BTreeMap<String, String>,
+) -> Result<Json<SettingsPayload>, AppError> {
+    if !errors.is_empty() {
+        let details: Vec<String> = errors
+            .iter()
+            .map(|(field, error)| format!("{}: {}", field, error))
+            .collect();
+        return Err(AppError::bad_request(format!(
+            "Invalid settings: {}",
+            details.join("; ")
+        )));
+    }
+    let payload: SettingsUpdate = serde_json::from_value(payload)
+        .map_err(|err| AppError::bad_request(format!("Invalid settings: {}", err)))?;
+    let empty_message = payload
+        .empty_message
+        .map(|message| message.trim().to_string());
     let row = sqlx::query(
-        "SELECT id, created_at, image_path, label, description, confidence, tags, share_token \
-         FROM entries WHERE id = $1",
+        "UPDATE settings SET is_public = COALESCE($1, is_public), \
+         auto_share = COALESCE($2, auto_share), \
+         empty_message = CASE WHEN $3::text IS NULL THEN empty_message ELSE NULLIF($3, '') END, \
+         updated_at = NOW() \
+         WHERE id = 1 RETURNING is_public, auto_share, empty_message",
     )
-    .bind(id)
-    .fetch_optional(&state.db)
+    .bind(payload.is_public)
+    .bind(payload.auto_share)
+    .bind(empty_message)
+    .fetch_one(&state.db)
     .await?;
 
-    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
-    Ok(Json(entry_detail_from_row(row)))
+    Ok(Json(SettingsPayload {
+        is_public: row.get("is_public"),
+        auto_share: row.get("auto_share"),
+        empty_message: row.get("empty_message"),
+    }))
 }
 
-async fn get_shared_entry(
+async fn list_entries(
     State(state): State<Arc<AppState>>,
-    Path(token): Path<String>,
-) -> Result<Json<EntryDetail>, AppError> {
-    let row = sqlx::query(
-        "SELECT id, created_at, image_path, label, description, confidence, tags, share_token \
-         FROM entries WHERE share_token = $1",
-    )
-    .bind(token)
-    .fetch_optional(&state.db)
-    .await?;
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(params): Query<ListParams>,
+    Query(page): Query<PageParams>,
+) -> Result<Response, AppError> {
+    let order = ListOrder::from_params(&params)?;
 
-    let row = row.ok_or_else(|| AppError::not_found("Share link not found"))?;
-    Ok(Json(entry_detail_from_row(row)))
+    // Any change to any entry (soft deletes included) moves the newest `updated_at`;
+    // purges also change the count. The key covers the query, as each one is its own
+    // representation, and the default random seed, since that changes daily.
+    let (newest, rows): (Option<DateTime<Utc>>, i64) =
+        sqlx::query_as("SELECT MAX(updated_at), COUNT(*) FROM entries")
+            .fetch_one(&state.db)
+            .await?;
+    let newest = newest.unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+    let seed = match &order {
+        ListOrder::Random { seed } => seed.as_str(),
+        ListOrder::Newest => "",
+    };
+    let validators = Validators::new(
+        newest,
+        &format!(
+            "{}:{}:{}:{}:{}",
+            newest.timestamp_micros(),
+            rows,
+            raw_query.unwrap_or_default(),
+            seed,
+            params.shared_only
+        ),
+    );
+    if validators.matches(&headers) {
+        return Ok(validators.not_modified());
+    }
+    let mut response = list_entries_response(state, params, page, order).await?;
+    validators.apply(&mut response);
+    Ok(response)
 }
 
-fn entry_summary_from_row(row: sqlx::postgres::PgRow) -> EntrySummary {
-    let share_token: Option<String> = row.get("share_token");
-    EntrySummary {
-        id: row.get("id"),
-        created_at: row.get("created_at"),
-        image_url: format!("/media/{}", row.get::<String, _>("image_path")),
-        label: row.get("label"),
-        description: row.get("description"),
-        confidence: row.get("confidence"),
-        tags: row.get::<Vec<String>, _>("tags"),
-        shared: share_token.is_some(),
+async fn list_entries_response(
+    state: Arc<AppState>,
+    params: ListParams,
+    page: PageParams,
+    order: ListOrder,
+) -> Result<Response, AppError> {
+    if params.stream {
+        return Ok(stream_entries(state, params, order));
+    }
+
+    let page = Pagination::from_params(&state, &page)?;
+    if page.after.is_some() && matches!(order, ListOrder::Random { .. }) {
+        return Err(AppError::bad_request(
+            "after can't be combined with order=random; page with offset and the same seed",
+        ));
+    }
+    let mut query = QueryBuilder::<Postgres>::new(format!(
+        "SELECT {} FROM entries WHERE deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ));
+    push_list_filters(&mut query, &state, &params);
+    page.push_after(&mut query);
+    order.push(&mut query);
+    page.push(&mut query);
+    let rows = query.build().fetch_all(&state.db).await?;
+
+    // A short page is the last one; random order is paged by offset instead.
+    let next_cursor = match rows.last() {
+        Some(row) if rows.len() == page.limit && matches!(order, ListOrder::Newest) => {
+            Some(encode_cursor(ListCursor {
+                is_pinned: row.get("is_pinned"),
+                created_at: row.get("created_at"),
+                id: row.get("id"),
+            }))
+        }
+        _ => None,
+    };
+
+    let mut count =
+        QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM entries WHERE deleted_at IS NULL");
+    push_list_filters(&mut count, &state, &params);
+    let total: i64 = count.build_query_scalar().fetch_one(&state.db).await?;
+
+    let entries: Vec<EntrySummary> = rows
+        .into_iter()
+        .map(|row| {
+            let mut entry = entry_summary_from_row(&state, row);
+            if let Some(max_tags) = params.max_tags {
+                entry.tags.truncate(max_tags);
+            }
+            entry
+        })
+        .collect();
+    let mut response = ([("x-total-count", total.to_string())], Json(entries)).into_response();
+    if let Some(cursor) = next_cursor
+        && let Ok(value) = header::HeaderValue::from_str(&cursor)
+    {
+        response.headers_mut().insert("x-next-cursor", value);
     }
+    if let ListOrder::Random { seed } = &order
+        && let Ok(value) = header::HeaderValue::from_str(seed)
+    {
+        response.headers_mut().insert("x-random-seed", value);
+    }
+    Ok(response)
 }
 
-fn entry_detail_from_row(row: sqlx::postgres::PgRow) -> EntryDetail {
-    let share_token: Option<String> = row.get("share_token");
-    let share_url = share_token
-        .as_ref()
-        .map(|token| format!("/share/{}", token));
+/// Writes all matching summaries as a JSON array straight from a database cursor, so
+/// memory stays flat however large the collection is. Pagination does not apply.
+///
+/// A database error mid-stream aborts the response, leaving the array unterminated.
+fn stream_entries(state: Arc<AppState>, params: ListParams, order: ListOrder) -> Response {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(16);
+    tokio::spawn(async move {
+        let mut query = QueryBuilder::<Postgres>::new(format!(
+            "SELECT {} FROM entries WHERE deleted_at IS NULL",
+            ENTRY_COLUMNS
+        ));
+        push_list_filters(&mut query, &state, &params);
+        order.push(&mut query);
+        let mut rows = query.build().fetch(&state.db);
 
-    EntryDetail {
-        id: row.get("id"),
-        created_at: row.get("created_at"),
-        image_url: format!("/media/{}", row.get::<String, _>("image_path")),
-        label: row.get("label"),
-        description: row.get("description"),
-        confidence: row.get("confidence"),
-        tags: row.get::<Vec<String>, _>("tags"),
-        shared: share_token.is_some(),
-        share_url,
+        let mut separator = "[";
+        while let Some(row) = rows.next().await {
+            let chunk = match row {
+                Ok(row) => {
+                    let mut entry = entry_summary_from_row(&state, row);
+                    if let Some(max_tags) = params.max_tags {
+                        entry.tags.truncate(max_tags);
+                    }
+                    let mut chunk = separator.as_bytes().to_vec();
+                    chunk.extend(serde_json::to_vec(&entry).unwrap_or_default());
+                    separator = ",";
+                    Ok(Bytes::from(chunk))
+                }
+                Err(err) => {
+                    error!("streaming entries failed: {}", err);
+                    Err(std::io::Error::other(err))
+                }
+            };
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_err() || failed {
+                return;
+            }
+        }
+        let end = if separator == "[" { "[]" } else { "]" };
+        let _ = tx.send(Ok(Bytes::from_static(end.as_bytes()))).await;
+    });
+
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+/// An entry's device name: the EXIF model, prefixed with the make unless the model
+/// already starts with it ("Canon EOS R5", not "Canon Canon EOS R5").
+const DEVICE_LABEL_SQL: &str = "CASE \
+     WHEN camera_make IS NULL AND camera_model IS NULL THEN 'unknown' \
+     WHEN camera_make IS NULL THEN camera_model \
+     WHEN camera_model IS NULL THEN camera_make \
+     WHEN starts_with(lower(camera_model), lower(camera_make)) THEN camera_model \
+     ELSE camera_make || ' ' || camera_model END";
+
+/// Appends the `ListParams` filters as `AND ...` clauses to a query over `entries`.
+fn push_list_filters(query: &mut QueryBuilder<'_, Postgres>, state: &AppState, params: &ListParams) {
+    if params.shared_only {
+        query.push(" AND share_token IS NOT NULL");
+    }
+    let tag = params
+        .tag
+        .as_deref()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty());
+    if let Some(tag) = tag {
+        query
+            .push(" AND tags && ")
+            .push_bind(expand_tag_synonyms(state, &tag))
+            .push("::text[]");
+    }
+    // Tags without synonyms go into one containment check; a tag with synonyms is
+    // satisfied by any of them, so it gets an overlap check of its own.
+    let mut required = Vec::new();
+    for tag in params.tags.as_deref().unwrap_or("").split(',') {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            continue;
+        }
+        match state.tag_synonyms.get(&tag) {
+            Some(group) => {
+                query
+                    .push(" AND tags && ")
+                    .push_bind(group.clone())
+                    .push("::text[]");
+            }
+            None if !required.contains(&tag) => required.push(tag),
+            None => {}
+        }
+    }
+    if !required.is_empty() {
+        query.push(" AND tags @> ").push_bind(required).push("::text[]");
+    }
+    if let Some(needs_review) = params.needs_review {
+        query.push(" AND needs_review = ").push_bind(needs_review);
+    }
+    if let Some(verified) = params.verified {
+        query.push(" AND verified = ").push_bind(verified);
+    }
+    if let Some(q) = params.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        push_search_query(query, q);
+    }
+    if let Some(device) = params.device.as_deref().map(str::trim).filter(|device| !device.is_empty()) {
+        query
+            .push(format!(" AND lower({}) = lower(", DEVICE_LABEL_SQL))
+            .push_bind(device.to_string())
+            .push(")");
     }
 }
 
-async fn create_entry(
-    State(state): State<Arc<AppState>>,
-    mut multipart: Multipart,
-) -> Result<Json<CreateEntryResponse>, AppError> {
-    let mut image_bytes: Option<Bytes> = None;
-    let mut image_mime: Option<String> = None;
+/// Queries shorter than this are matched as substrings, since full-text search would
+/// drop or stem them into nothing useful.
+const SEARCH_MIN_FULL_TEXT_CHARS: usize = 3;
 
-    while let Some(field) = multipart.next_field().await? {
-        if let Some(name) = field.name() {
-            if name == "image" {
-                image_mime = field.content_type().map(|v| v.to_string());
-                image_bytes = Some(field.bytes().await?);
-                break;
+fn push_search_query(query: &mut QueryBuilder<'_, Postgres>, q: &str) {
+    if q.chars().count() < SEARCH_MIN_FULL_TEXT_CHARS {
+        let pattern = format!(
+            "%{}%",
+            q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+        );
+        query
+            .push(" AND (label ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR description ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    } else {
+        query
+            .push(" AND search_vector @@ plainto_tsquery('english', ")
+            .push_bind(q.to_string())
+            .push(")");
+    }
+}
+
+/// Trims and lowercases the model's tags and drops repeats, keeping the first position.
+///
+/// With `collapse_plurals`, tags that only differ by a plural ending (`tree` / `trees`) are
+/// also merged, spelled in the singular when that form was given. A lone plural is left alone.
+fn normalize_tags(tags: &[String], collapse_plurals: bool) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    let mut keys: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            continue;
+        }
+        let key = match collapse_plurals {
+            true => singular_tag(&tag),
+            false => tag.clone(),
+        };
+        match keys.iter().position(|known| *known == key) {
+            Some(index) if tag == key => normalized[index] = tag,
+            Some(_) => {}
+            None => {
+                keys.push(key);
+                normalized.push(tag);
             }
         }
     }
+    normalized
+}
 
-    let bytes = image_bytes.ok_or_else(|| AppError::bad_request("Missing image field"))?;
-    let mime = image_mime.unwrap_or_else(|| "image/jpeg".to_string());
+/// Nouns ending in `-ies` or `-s` that aren't plurals of something shorter, beyond the
+/// `-ss`, `-us` and `-is` endings `singular_tag` already leaves alone.
+const INVARIANT_TAG_NOUNS: &[&str] = &["species", "series", "lens"];
 
-    let (width, height) = match image::load_from_memory(&bytes) {
-        Ok(img) => (Some(img.width() as i32), Some(img.height() as i32)),
-        Err(_) => (None, None),
+/// The singular of a tag's last word, by the common English endings; a guess, not a dictionary.
+fn singular_tag(tag: &str) -> String {
+    let word = tag.rsplit(' ').next().unwrap_or(tag);
+    let stem = tag.len() - word.len();
+    if INVARIANT_TAG_NOUNS.contains(&word) || ["ss", "us", "is"].iter().any(|end| word.ends_with(end)) {
+        return tag.to_string();
+    }
+    let singular = if let Some(base) = word.strip_suffix("ies").filter(|base| base.len() > 1) {
+        format!("{}y", base)
+    } else if let Some(base) = word
+        .strip_suffix("es")
+        .filter(|base| ["s", "x", "z", "ch", "sh"].iter().any(|end| base.ends_with(end)))
+    {
+        base.to_string()
+    } else if let Some(base) = word.strip_suffix('s').filter(|base| base.len() > 1) {
+        base.to_string()
+    } else {
+        word.to_string()
     };
+    format!("{}{}", &tag[..stem], singular)
+}
 
-    let id = Uuid::new_v4();
-    let extension = match mime.as_str() {
-        "image/png" => "png",
-        "image/webp" => "webp",
-        _ => "jpg",
-    };
-    let filename = format!("images/{}.{}", id, extension);
-    let file_path = state.storage_dir.join(&filename);
-    tokio::fs::write(&file_path, &bytes).await?;
+/// Returns the tag plus every configured synonym of it, so filters match any spelling.
+fn expand_tag_synonyms(state: &AppState, tag: &str) -> Vec<String> {
+    match state.tag_synonyms.get(tag) {
+        Some(group) => group.clone(),
+        None => vec![tag.to_string()],
+    }
+}
 
-    let classification = match classify_image(&state, &bytes, &mime).await {
-        Ok(classification) => classification,
-        Err(err) => {
-            if let Err(remove_err) = tokio::fs::remove_file(&file_path).await {
-                error!("failed to remove image after classification error: {}", remove_err);
-            }
-            return Err(err);
+/// Most reference examples `FEW_SHOT_EXAMPLES` may list; each adds an image to every call.
+const MAX_FEW_SHOT_EXAMPLES: usize = 5;
+
+/// Examples are downscaled to this long edge, which is plenty to show what a label looks
+/// like and keeps the added tokens per call small.
+const FEW_SHOT_MAX_EDGE: u32 = 512;
+
+/// A labelled reference image sent ahead of the photo in `classify_image`.
+#[derive(Clone)]
+struct FewShotExample {
+    label: String,
+    /// Base64 JPEG.
+    data: String,
+}
+
+/// Parses `FEW_SHOT_EXAMPLES` (`path=label;path=label`) and loads the images, with relative
+/// paths resolved against `STORAGE_DIR` so stored entries (`images/<id>.jpg`) can be reused.
+fn load_few_shot_examples(
+    storage_dir: &std::path::Path,
+    value: &str,
+    quality: u8,
+) -> anyhow::Result<Vec<FewShotExample>> {
+    let specs: Vec<&str> = value.split(';').map(str::trim).filter(|spec| !spec.is_empty()).collect();
+    if specs.len() > MAX_FEW_SHOT_EXAMPLES {
+        anyhow::bail!(
+            "FEW_SHOT_EXAMPLES lists {} images; at most {} are allowed",
+            specs.len(),
+            MAX_FEW_SHOT_EXAMPLES
+        );
+    }
+    let mut examples = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let (path, label) = spec
+            .split_once('=')
+            .map(|(path, label)| (path.trim(), label.trim()))
+            .filter(|(path, label)| !path.is_empty() && !label.is_empty())
+            .ok_or_else(|| {
+                anyhow::anyhow!("Invalid FEW_SHOT_EXAMPLES entry '{}': expected path=label", spec)
+            })?;
+        let path = storage_dir.join(path);
+        let load = || -> anyhow::Result<DynamicImage> {
+            Ok(image::ImageReader::open(&path)?.with_guessed_format()?.decode()?)
+        };
+        let img = load().map_err(|err| {
+            anyhow::anyhow!("Failed to load FEW_SHOT_EXAMPLES image {}: {}", path.display(), err)
+        })?;
+        let img = img.resize(
+            FEW_SHOT_MAX_EDGE,
+            FEW_SHOT_MAX_EDGE,
+            image::imageops::FilterType::Triangle,
+        );
+        let jpeg = shrink_for_anthropic(&img, quality).map_err(|err| anyhow::anyhow!(err.message))?;
+        examples.push(FewShotExample {
+            label: label.to_string(),
+            data: base64::engine::general_purpose::STANDARD.encode(jpeg),
+        });
+    }
+    Ok(examples)
+}
+
+/// Bundled translations merged with `TAG_TRANSLATIONS_FILE`, which wins on conflicts.
+fn load_tag_translations() -> anyhow::Result<HashMap<String, HashMap<String, String>>> {
+    let mut translations: HashMap<String, HashMap<String, String>> =
+        serde_json::from_str(TAG_TRANSLATIONS)?;
+    if let Ok(path) = std::env::var("TAG_TRANSLATIONS_FILE")
+        && !path.trim().is_empty()
+    {
+        let text = std::fs::read_to_string(path.trim())
+            .map_err(|err| anyhow::anyhow!("Failed to read TAG_TRANSLATIONS_FILE {}: {}", path, err))?;
+        let extra: HashMap<String, HashMap<String, String>> = serde_json::from_str(&text)
+            .map_err(|err| anyhow::anyhow!("Invalid TAG_TRANSLATIONS_FILE {}: {}", path, err))?;
+        for (lang, labels) in extra {
+            translations.entry(lang).or_default().extend(labels);
         }
-    };
-    let raw_json = serde_json::to_value(&classification)?;
+    }
+    Ok(translations
+        .into_iter()
+        .map(|(lang, labels)| {
+            let labels = labels
+                .into_iter()
+                .map(|(tag, label)| (tag.to_lowercase(), label))
+                .collect();
+            (lang.to_lowercase(), labels)
+        })
+        .collect())
+}
 
-    sqlx::query(
-        "INSERT INTO entries (id, image_path, image_mime, image_width, image_height, label, description, confidence, tags, raw_json) \
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+/// Tags in use with their entry counts, labelled in `?lang=` when a translation exists.
+async fn list_tags(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TagsParams>,
+) -> Result<Json<Vec<TagCount>>, AppError> {
+    let labels = tag_labels(&state, params.lang)?;
+    let limit = Pagination::limit(&state, params.limit)?;
+
+    let rows = sqlx::query(
+        "SELECT tag, COUNT(*) AS count FROM entries, unnest(tags) AS tag \
+         WHERE deleted_at IS NULL GROUP BY tag ORDER BY count DESC, tag LIMIT $1",
     )
-    .bind(id)
-    .bind(&filename)
-    .bind(&mime)
-    .bind(width)
-    .bind(height)
-    .bind(&classification.label)
-    .bind(&classification.description)
-    .bind(classification.confidence)
-    .bind(&classification.tags)
-    .bind(raw_json)
-    .execute(&state.db)
+    .bind(limit as i64)
+    .fetch_all(&state.db)
     .await?;
+    Ok(Json(tag_counts_from_rows(labels, rows)))
+}
 
-    let row = sqlx::query(
-        "SELECT id, created_at, image_path, label, description, confidence, tags, share_token \
-         FROM entries WHERE id = $1",
-    )
-    .bind(id)
-    .fetch_one(&state.db)
+#[derive(Deserialize)]
+struct TagStatsParams {
+    from: Option<String>,
+    to: Option<String>,
+    lang: Option<String>,
+    limit: Option<i64>,
+}
+
+/// `list_tags` restricted to entries created between `from` and `to`.
+async fn tag_stats(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TagStatsParams>,
+) -> Result<Json<Vec<TagCount>>, AppError> {
+    let labels = tag_labels(&state, params.lang)?;
+    let limit = Pagination::limit(&state, params.limit)?;
+    let (from, to) = parse_day_range(params.from.as_deref(), params.to.as_deref())?;
+
+    let rows = sqlx::query(&format!(
+        "SELECT tag, COUNT(*) AS count FROM entries, unnest(tags) AS tag \
+         WHERE deleted_at IS NULL AND {} GROUP BY tag ORDER BY count DESC, tag LIMIT $3",
+        CREATED_DAY_RANGE_SQL
+    ))
+    .bind(from)
+    .bind(to)
+    .bind(limit as i64)
+    .fetch_all(&state.db)
     .await?;
+    Ok(Json(tag_counts_from_rows(labels, rows)))
+}
 
-    Ok(Json(CreateEntryResponse {
-        entry: entry_detail_from_row(row),
-    }))
+#[derive(Deserialize)]
+struct CooccurrenceParams {
+    tag: String,
+    lang: Option<String>,
+    limit: Option<i64>,
 }
 
-async fn soft_delete_entry(
+/// The tags that most often appear on entries alongside `tag` (or any of its synonyms).
+async fn tag_cooccurrence(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    let result = sqlx::query(
-        "UPDATE entries SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL",
+    Query(params): Query<CooccurrenceParams>,
+) -> Result<Json<Vec<TagCount>>, AppError> {
+    let labels = tag_labels(&state, params.lang)?;
+    let tag = params.tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(AppError::bad_request("tag is required"));
+    }
+    let limit = Pagination::limit(&state, params.limit)?;
+
+    let rows = sqlx::query(
+        "SELECT tag, COUNT(*) AS count FROM entries, unnest(tags) AS tag \
+         WHERE deleted_at IS NULL AND tags && $1::text[] AND NOT tag = ANY($1::text[]) \
+         GROUP BY tag ORDER BY count DESC, tag LIMIT $2",
     )
-    .bind(id)
-    .execute(&state.db)
+    .bind(expand_tag_synonyms(&state, &tag))
+    .bind(limit as i64)
+    .fetch_all(&state.db)
     .await?;
+    Ok(Json(tag_counts_from_rows(labels, rows)))
+}
 
-    if result.rows_affected() == 0 {
-        return Err(AppError::not_found("Entry not found"));
+/// The translation table for `lang`; `pt-br` falls back to `pt` when there is no regional one.
+fn tag_labels(
+    state: &AppState,
+    lang: Option<String>,
+) -> Result<Option<&HashMap<String, String>>, AppError> {
+    let lang = lang
+        .map(|lang| lang.trim().to_lowercase())
+        .filter(|lang| !lang.is_empty());
+    if let Some(lang) = &lang
+        && !is_valid_lang(lang)
+    {
+        return Err(AppError::bad_request(format!("Invalid lang: {}", lang)));
     }
+    Ok(lang.as_deref().and_then(|lang| {
+        state.tag_translations.get(lang).or_else(|| {
+            lang.split_once('-')
+                .and_then(|(base, _)| state.tag_translations.get(base))
+        })
+    }))
+}
 
-    Ok(Json(serde_json::json!({ "status": "deleted" })))
+/// Turns `tag, count` rows into `TagCount`s labelled from `labels`.
+fn tag_counts_from_rows(
+    labels: Option<&HashMap<String, String>>,
+    rows: Vec<sqlx::postgres::PgRow>,
+) -> Vec<TagCount> {
+    rows.into_iter()
+        .map(|row| {
+            let tag: String = row.get("tag");
+            let label = labels
+                .and_then(|labels| labels.get(&tag.to_lowercase()))
+                .cloned()
+                .unwrap_or_else(|| tag.clone());
+            TagCount {
+                tag,
+                label,
+                count: row.get("count"),
+            }
+        })
+        .collect()
 }
 
-async fn restore_entry(
+#[derive(Deserialize)]
+struct SimilarParams {
+    limit: Option<i64>,
+    /// Leave out entries more than this many bits (of 64) away.
+    max_distance: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct SimilarEntry {
+    #[serde(flatten)]
+    entry: EntrySummary,
+    /// Differing bits between the perceptual hashes; 0 is a near-identical image.
+    distance: i32,
+}
+
+/// Ranks existing entries by how much they look like an uploaded image, closest first,
+/// without saving anything. Entries stored before perceptual hashing have no hash until
+/// `POST /api/admin/backfill-hashes` runs and aren't considered.
+async fn similar_entries(
     State(state): State<Arc<AppState>>,
-    Path(id): Path<Uuid>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    let row = sqlx::query("SELECT deleted_at FROM entries WHERE id = $1")
-        .bind(id)
-        .fetch_optional(&state.db)
-        .await?;
+    Query(params): Query<SimilarParams>,
+    mut multipart: Multipart,
+) -> Result<Json<Vec<SimilarEntry>>, AppError> {
+    let limit = Pagination::limit(&state, params.limit)?;
+    let mut image = None;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("image") {
+            let declared_mime = field.content_type().map(|v| v.to_string());
+            image = Some((field.bytes().await?, declared_mime));
+        }
+    }
+    let (bytes, declared_mime) = image.ok_or_else(|| AppError::bad_request("Missing image field"))?;
+    detect_image_mime(&bytes, declared_mime.as_deref())?;
+    let img = match decode_image(&state, &bytes) {
+        Ok(img) => img,
+        Err(err @ image::ImageError::Limits(_)) => return Err(err.into()),
+        Err(err) => {
+            return Err(AppError::unprocessable(format!("Could not decode image: {}", err)));
+        }
+    };
+    let hash = perceptual_hash(&img);
+
+    let distance_sql = "length(replace(((phash # $1)::bit(64))::text, '0', ''))";
+    let rows = sqlx::query(&format!(
+        "SELECT {columns}, {distance} AS distance FROM entries \
+         WHERE deleted_at IS NULL AND phash IS NOT NULL AND ($2::int IS NULL OR {distance} <= $2) \
+         ORDER BY distance, created_at DESC LIMIT $3",
+        columns = ENTRY_COLUMNS,
+        distance = distance_sql
+    ))
+    .bind(hash)
+    .bind(params.max_distance.map(|distance| distance as i32))
+    .bind(limit as i64)
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(
+        rows.into_iter()
+            .map(|row| {
+                let distance: i32 = row.get("distance");
+                SimilarEntry {
+                    entry: entry_summary_from_row(&state, row),
+                    distance,
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Entry counts per capture device, most used first.
+async fn list_devices(State(state): State<Arc<AppState>>) -> Result<Json<Vec<DeviceCount>>, AppError> {
+    let rows = sqlx::query(&format!(
+        "SELECT {} AS device, camera_make, camera_model, COUNT(*) AS count FROM entries \
+         WHERE deleted_at IS NULL GROUP BY camera_make, camera_model ORDER BY count DESC, device",
+        DEVICE_LABEL_SQL
+    ))
+    .fetch_all(&state.db)
+    .await?;
+
+    let devices = rows
+        .into_iter()
+        .map(|row| DeviceCount {
+            device: row.get("device"),
+            make: row.get("camera_make"),
+            model: row.get("camera_model"),
+            count: row.get("count"),
+        })
+        .collect();
+    Ok(Json(devices))
+}
+
+/// Colors for every tag in use or with an assigned color, sorted by tag.
+async fn get_tag_colors(State(state): State<Arc<AppState>>) -> Result<Json<Vec<TagColor>>, AppError> {
+    let rows = sqlx::query(
+        "SELECT tags.tag, tag_colors.color FROM \
+         (SELECT DISTINCT unnest(tags) AS tag FROM entries WHERE deleted_at IS NULL \
+          UNION SELECT tag FROM tag_colors) AS tags \
+         LEFT JOIN tag_colors ON tag_colors.tag = tags.tag ORDER BY tags.tag",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let colors = rows
+        .into_iter()
+        .map(|row| {
+            let tag: String = row.get("tag");
+            let color: Option<String> = row.get("color");
+            TagColor {
+                assigned: color.is_some(),
+                color: color.unwrap_or_else(|| default_tag_color(&tag)),
+                tag,
+            }
+        })
+        .collect();
+    Ok(Json(colors))
+}
+
+async fn update_tag_colors(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<TagColorsUpdate>,
+) -> Result<Json<Vec<TagColor>>, AppError> {
+    let mut tx = state.db.begin().await?;
+    for (tag, color) in payload.colors {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            return Err(AppError::bad_request("Tag names must not be empty"));
+        }
+        match color {
+            Some(color) => {
+                let color = normalize_hex_color(&color).ok_or_else(|| {
+                    AppError::bad_request(format!(
+                        "Invalid color for '{}': expected #rgb or #rrggbb, got '{}'",
+                        tag, color
+                    ))
+                })?;
+                sqlx::query(
+                    "INSERT INTO tag_colors (tag, color) VALUES ($1, $2) \
+                     ON CONFLICT (tag) DO UPDATE SET color = EXCLUDED.color, updated_at = NOW()",
+                )
+                .bind(&tag)
+                .bind(color)
+                .execute(&mut *tx)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM tag_colors WHERE tag = $1")
+                    .bind(&tag)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+    }
+    tx.commit().await?;
+
+    get_tag_colors(State(state)).await
+}
+
+/// Lowercase `#rrggbb` for a `#rgb` or `#rrggbb` input.
+fn normalize_hex_color(value: &str) -> Option<String> {
+    let hex = value.trim().strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = hex.to_ascii_lowercase();
+    match hex.len() {
+        6 => Some(format!("#{}", hex)),
+        3 => Some(hex.chars().fold(String::from("#"), |mut out, c| {
+            out.push(c);
+            out.push(c);
+            out
+        })),
+        _ => None,
+    }
+}
+
+/// A stable pastel color derived from the tag name, so unassigned tags look the same everywhere.
+fn default_tag_color(tag: &str) -> String {
+    let digest = Sha256::digest(tag.as_bytes());
+    let hue = u16::from_be_bytes([digest[0], digest[1]]) as f64 / 65536.0 * 360.0;
+    let (saturation, lightness) = (0.55, 0.72);
+
+    let chroma = (1.0 - (2.0 * lightness - 1.0_f64).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let offset = lightness - chroma / 2.0;
+    let channel = |value: f64| ((value + offset) * 255.0).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+}
+
+/// Parses `TAG_SYNONYMS` (`bird|avian,fungus|fungi`) into a lookup from each tag to its group.
+fn parse_tag_synonyms(value: &str) -> HashMap<String, Vec<String>> {
+    let mut synonyms = HashMap::new();
+    for group in value.split(',') {
+        let members: Vec<String> = group
+            .split('|')
+            .map(|tag| tag.trim().to_lowercase())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        if members.len() < 2 {
+            continue;
+        }
+        for member in &members {
+            synonyms.insert(member.clone(), members.clone());
+        }
+    }
+    synonyms
+}
+
+async fn list_public_entries(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    raw_query: RawQuery,
+    Query(mut params): Query<ListParams>,
+    page: Query<PageParams>,
+) -> Result<Response, AppError> {
+    require_public_collection(&state).await?;
+    // A public collection shows what was shared, not everything.
+    params.shared_only = true;
+    list_entries(State(state), headers, raw_query, Query(params), page).await
+}
+
+async fn require_public_collection(state: &AppState) -> Result<(), AppError> {
+    let row = sqlx::query("SELECT is_public FROM settings WHERE id = 1")
+        .fetch_one(&state.db)
+        .await?;
+    let is_public: bool = row.get("is_public");
+
+    if !is_public {
+        return Err(AppError::not_found("Collection not public"));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct PublicMeta {
+    entry_count: i64,
+    /// The configured empty-state message, only while there is nothing to show.
+    empty_message: Option<String>,
+}
+
+/// What a public page needs before it renders the feed.
+async fn public_meta(State(state): State<Arc<AppState>>) -> Result<Json<PublicMeta>, AppError> {
+    require_public_collection(&state).await?;
+    let row = sqlx::query(
+        "SELECT (SELECT COUNT(*) FROM entries WHERE deleted_at IS NULL AND share_token IS NOT NULL) \
+         AS entry_count, empty_message FROM settings WHERE id = 1",
+    )
+    .fetch_one(&state.db)
+    .await?;
+    let entry_count: i64 = row.get("entry_count");
+    let empty_message: Option<String> = row.get("empty_message");
+
+    Ok(Json(PublicMeta {
+        entry_count,
+        empty_message: empty_message.filter(|_| entry_count == 0),
+    }))
+}
+
+/// Default and maximum `count` for the recent-entries endpoints.
+const RECENT_ENTRIES_DEFAULT: i64 = 6;
+const RECENT_ENTRIES_MAX: i64 = 24;
+
+#[derive(Deserialize)]
+struct RecentParams {
+    count: Option<i64>,
+}
+
+/// The newest few entries, for widgets that don't want to deal with pagination.
+async fn list_recent_entries(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<RecentParams>,
+) -> Result<Json<Vec<EntrySummary>>, AppError> {
+    recent_entries(&state, params.count, false).await.map(Json)
+}
+
+async fn list_public_recent_entries(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<RecentParams>,
+) -> Result<Json<Vec<EntrySummary>>, AppError> {
+    require_public_collection(&state).await?;
+    recent_entries(&state, params.count, true).await.map(Json)
+}
+
+/// `shared_only` limits the result to entries with a share link, as the public endpoints do.
+async fn recent_entries(
+    state: &AppState,
+    count: Option<i64>,
+    shared_only: bool,
+) -> Result<Vec<EntrySummary>, AppError> {
+    let count = match count {
+        Some(count) if count < 1 => {
+            return Err(AppError::bad_request("count must be at least 1"));
+        }
+        Some(count) => count.min(RECENT_ENTRIES_MAX),
+        None => RECENT_ENTRIES_DEFAULT,
+    };
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM entries WHERE deleted_at IS NULL AND (share_token IS NOT NULL OR NOT $2) \
+         ORDER BY created_at DESC, id DESC LIMIT $1",
+        ENTRY_COLUMNS
+    ))
+    .bind(count)
+    .bind(shared_only)
+    .fetch_all(&state.db)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| entry_summary_from_row(state, row))
+        .collect())
+}
+
+/// Every geotagged entry as a GeoJSON `FeatureCollection` of points, newest first.
+async fn entries_geojson(State(state): State<Arc<AppState>>) -> Result<Response, AppError> {
+    geojson_response(&state, false).await
+}
+
+async fn public_entries_geojson(State(state): State<Arc<AppState>>) -> Result<Response, AppError> {
+    require_public_collection(&state).await?;
+    geojson_response(&state, true).await
+}
+
+async fn geojson_response(state: &AppState, shared_only: bool) -> Result<Response, AppError> {
+    let rows = sqlx::query(
+        "SELECT id, label, image_path, lat, lng FROM entries \
+         WHERE deleted_at IS NULL AND lat IS NOT NULL AND lng IS NOT NULL \
+         AND (share_token IS NOT NULL OR NOT $1) ORDER BY created_at DESC, id DESC",
+    )
+    .bind(shared_only)
+    .fetch_all(&state.db)
+    .await?;
+
+    let features: Vec<serde_json::Value> = rows
+        .into_iter()
+        .map(|row| {
+            let (lat, lng): (f64, f64) = (row.get("lat"), row.get("lng"));
+            serde_json::json!({
+                "type": "Feature",
+                // GeoJSON positions are longitude first.
+                "geometry": { "type": "Point", "coordinates": [lng, lat] },
+                "properties": {
+                    "id": row.get::<Uuid, _>("id"),
+                    "label": row.get::<String, _>("label"),
+                    "image_url": media_url(state, &row.get::<String, _>("image_path")),
+                },
+            })
+        })
+        .collect();
+    let body = serde_json::json!({ "type": "FeatureCollection", "features": features });
+    Ok(([(header::CONTENT_TYPE, "application/geo+json")], Json(body)).into_response())
+}
+
+/// How far behind the poll the returned cursor is set. `NOW()` is a transaction's start
+/// time, so a write can commit after a poll with an `updated_at` before it; the next poll
+/// looks back this far to pick it up, and may return the last few changes again.
+const CHANGES_CURSOR_LAG_SECS: i64 = 10;
+
+/// Returns everything that changed since `since` (a cursor from a previous call).
+///
+/// Entries created after the cursor are returned in full; older entries that were
+/// edited, shared or restored only carry their mutable fields, so a client that no
+/// longer has one cached should fetch it with `get_entry`. Entries purged since the
+/// cursor are listed under `deleted` from their tombstones.
+async fn list_entry_changes(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ChangesParams>,
+) -> Result<Json<ChangesResponse>, AppError> {
+    let cursor = match params.since.as_deref() {
+        Some(cursor) => Some(
+            DateTime::parse_from_rfc3339(cursor)
+                .map_err(|_| AppError::bad_request("Invalid cursor"))?
+                .with_timezone(&Utc),
+        ),
+        None => None,
+    };
+    let since = cursor.unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+
+    let now: DateTime<Utc> = sqlx::query_scalar("SELECT NOW()")
+        .fetch_one(&state.db)
+        .await?;
+
+    let rows = sqlx::query(&format!(
+        "SELECT {}, updated_at, deleted_at FROM entries \
+         WHERE updated_at > $1 AND updated_at <= $2 ORDER BY updated_at ASC",
+        ENTRY_COLUMNS
+    ))
+    .bind(since)
+    .bind(now)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut response = ChangesResponse {
+        created: Vec::new(),
+        updated: Vec::new(),
+        deleted: Vec::new(),
+        cursor: (now - Duration::seconds(CHANGES_CURSOR_LAG_SECS))
+            .max(since)
+            .to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+    };
+
+    for row in rows {
+        let deleted_at: Option<DateTime<Utc>> = row.get("deleted_at");
+        let created_at: DateTime<Utc> = row.get("created_at");
+        if deleted_at.is_some() {
+            response.deleted.push(row.get("id"));
+        } else if created_at > since {
+            response.created.push(entry_summary_from_row(&state, row));
+        } else {
+            let share_token: Option<String> = row.get("share_token");
+            response.updated.push(EntryChange {
+                id: row.get("id"),
+                updated_at: row.get("updated_at"),
+                label: row.get("label"),
+                description: row.get("description"),
+                confidence: row.get("confidence"),
+                tags: row.get::<Vec<String>, _>("tags"),
+                shared: share_token.is_some(),
+            });
+        }
+    }
+    // A first sync has nothing cached that could have been purged.
+    if cursor.is_some() {
+        let purged: Vec<Uuid> = sqlx::query_scalar(
+            "SELECT id FROM entry_tombstones WHERE purged_at > $1 AND purged_at <= $2 \
+             ORDER BY purged_at ASC",
+        )
+        .bind(since)
+        .bind(now)
+        .fetch_all(&state.db)
+        .await?;
+        response.deleted.extend(purged);
+    }
+
+    Ok(Json(response))
+}
+
+async fn get_entry(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let row = sqlx::query(&format!(
+        "SELECT {}, updated_at FROM entries WHERE id = $1 AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    let updated_at: DateTime<Utc> = row.get("updated_at");
+    let validators = Validators::new(updated_at, &format!("{}:{}", id, updated_at.timestamp_micros()));
+    if validators.matches(&headers) {
+        return Ok(validators.not_modified());
+    }
+    let mut response = Json(entry_detail_from_row(&state, row)).into_response();
+    validators.apply(&mut response);
+    Ok(response)
+}
+
+/// `ETag` / `Last-Modified` for a representation, so polling clients can revalidate cheaply.
+struct Validators {
+    etag: String,
+    last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// A weak ETag over `key`, which must change whenever the representation does.
+    fn new(last_modified: DateTime<Utc>, key: &str) -> Self {
+        let digest = hex::encode(&Sha256::digest(key.as_bytes())[..12]);
+        Self {
+            etag: format!("W/\"{}\"", digest),
+            last_modified,
+        }
+    }
+
+    /// Whether the client's cached copy is current. `If-None-Match` wins over
+    /// `If-Modified-Since` when both are sent, as HTTP specifies.
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+            let Ok(value) = value.to_str() else {
+                return false;
+            };
+            // Weak comparison: `W/` prefixes don't matter.
+            let ours = self.etag.trim_start_matches("W/");
+            return value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == ours);
+        }
+        headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            // HTTP dates have whole seconds only.
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
+
+    fn apply(&self, response: &mut Response) {
+        let headers = response.headers_mut();
+        if let Ok(value) = header::HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, value);
+        }
+        let last_modified = self.last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = header::HeaderValue::from_str(&last_modified) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+
+    fn not_modified(&self) -> Response {
+        let mut response = StatusCode::NOT_MODIFIED.into_response();
+        self.apply(&mut response);
+        response
+    }
+}
+
+/// Upper bound on ids accepted by `batch_get_entries`.
+const MAX_BATCH_GET_IDS: usize = 200;
+
+async fn batch_get_entries(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<BatchGetPayload>,
+) -> Result<Json<BatchGetResponse>, AppError> {
+    if payload.ids.len() > MAX_BATCH_GET_IDS {
+        return Err(AppError::bad_request(format!(
+            "At most {} ids can be fetched at once",
+            MAX_BATCH_GET_IDS
+        )));
+    }
+
+    let rows = sqlx::query(&format!(
+        "SELECT {} FROM entries WHERE id = ANY($1) AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(&payload.ids)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut by_id: HashMap<Uuid, sqlx::postgres::PgRow> =
+        rows.into_iter().map(|row| (row.get("id"), row)).collect();
+    let mut response = BatchGetResponse {
+        entries: Vec::new(),
+        missing: Vec::new(),
+    };
+    for id in &payload.ids {
+        match by_id.remove(id) {
+            Some(row) => response.entries.push(entry_detail_from_row(&state, row)),
+            None if response.entries.iter().any(|entry| entry.id == *id) => {}
+            None => response.missing.push(*id),
+        }
+    }
+    Ok(Json(response))
+}
+
+async fn get_shared_entry(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Result<Json<EntryDetail>, AppError> {
+    let row = fetch_shared_entry(&state, &token).await?;
+    let row = row.ok_or_else(|| AppError::not_found("Share link not found"))?;
+    Ok(Json(entry_detail_from_row(&state, row)))
+}
+
+async fn fetch_shared_entry(
+    state: &AppState,
+    token: &str,
+) -> Result<Option<sqlx::postgres::PgRow>, AppError> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM entries WHERE share_token = $1 AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(token)
+    .fetch_optional(&state.db)
+    .await?;
+    Ok(row)
+}
+
+/// Plain HTML for a shared entry so link previews and no-JS clients see something useful.
+async fn shared_entry_page(
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let Some(row) = fetch_shared_entry(&state, &token).await? else {
+        let page = render_page("Share link not found", "", "<h1>Share link not found</h1>");
+        return Ok((StatusCode::NOT_FOUND, Html(page)).into_response());
+    };
+    let entry = entry_detail_from_row(&state, row);
+
+    let title = escape_html(&entry.label);
+    let description = escape_html(&entry.description);
+    let image_url = escape_html(&absolute_url(&headers, &entry.image_url));
+    let page_url = escape_html(&absolute_url(&headers, &format!("/share/{}", token)));
+    let tags: String = entry
+        .tags
+        .iter()
+        .map(|tag| format!("<li>{}</li>", escape_html(tag)))
+        .collect();
+
+    let meta = format!(
+        r#"<meta property="og:type" content="article">
+<meta property="og:title" content="{title}">
+<meta property="og:description" content="{description}">
+<meta property="og:image" content="{image_url}">
+<meta property="og:url" content="{page_url}">
+<meta name="twitter:card" content="summary_large_image">
+<meta name="twitter:title" content="{title}">
+<meta name="twitter:description" content="{description}">
+<meta name="twitter:image" content="{image_url}">"#
+    );
+    let body = format!(
+        r#"<img src="{image_url}" alt="{title}">
+<h1>{title}</h1>
+<p>{description}</p>
+<ul>{tags}</ul>
+<p><a href="{page_url}">Open in NaturaDex</a></p>"#
+    );
+
+    Ok(Html(render_page(&entry.label, &meta, &body)).into_response())
+}
+
+fn render_page(title: &str, meta: &str, body: &str) -> String {
+    format!(
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{} · NaturaDex</title>
+{}
+<style>body{{font-family:sans-serif;max-width:40rem;margin:2rem auto;padding:0 1rem;color:#24352a}}img{{max-width:100%;border-radius:12px}}ul{{display:flex;flex-wrap:wrap;gap:.5rem;padding:0;list-style:none}}li{{background:#e8f1e4;border-radius:999px;padding:.2rem .7rem}}</style>
+</head>
+<body>
+{}
+</body>
+</html>
+"#,
+        escape_html(title),
+        meta,
+        body
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Makes a URL absolute, using the request's host when `PUBLIC_BASE_URL` is unset.
+fn absolute_url(headers: &HeaderMap, url: &str) -> String {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return url.to_string();
+    }
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("http");
+    format!("{}://{}{}", scheme, host, url)
+}
+
+/// Prefixes a server-relative path with `PUBLIC_BASE_URL` when one is configured.
+fn public_url(state: &AppState, path: &str) -> String {
+    match &state.public_base_url {
+        Some(base) => format!("{}{}", base, path),
+        None => path.to_string(),
+    }
+}
+
+fn media_url(state: &AppState, image_path: &str) -> String {
+    public_url(state, &format!("/media/{}", image_path))
+}
+
+fn entry_summary_from_row(state: &AppState, row: sqlx::postgres::PgRow) -> EntrySummary {
+    let share_token: Option<String> = row.get("share_token");
+    let thumb_path: Option<String> = row.get("thumb_path");
+    let display_title = state.title_template.render(|field| match field {
+        TitleField::Label => row.get("label"),
+        TitleField::Date => row
+            .get::<DateTime<Utc>, _>("created_at")
+            .format("%Y-%m-%d")
+            .to_string(),
+        TitleField::Confidence => row
+            .get::<Option<f64>, _>("confidence")
+            .map(|confidence| format!("{:.0}%", confidence * 100.0))
+            .unwrap_or_default(),
+        TitleField::Device => device_label(row.get("camera_make"), row.get("camera_model")),
+    });
+    EntrySummary {
+        id: row.get("id"),
+        created_at: row.get("created_at"),
+        image_url: media_url(state, &row.get::<String, _>("image_path")),
+        thumb_url: thumb_path.map(|path| media_url(state, &path)),
+        label: row.get("label"),
+        description: row.get("description"),
+        confidence: row.get("confidence"),
+        tags: row.get::<Vec<String>, _>("tags"),
+        shared: share_token.is_some(),
+        is_pinned: row.get("is_pinned"),
+        lat: row.get("lat"),
+        lng: row.get("lng"),
+        verified: row.get("verified"),
+        model: row.get("model"),
+        display_title,
+    }
+}
+
+/// `DEVICE_LABEL_SQL` for an already loaded row, except that unknown is empty.
+fn device_label(make: Option<String>, model: Option<String>) -> String {
+    match (make, model) {
+        (None, None) => String::new(),
+        (Some(make), None) => make,
+        (None, Some(model)) => model,
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => model,
+        (Some(make), Some(model)) => format!("{} {}", make, model),
+    }
+}
+
+fn entry_detail_from_row(state: &AppState, row: sqlx::postgres::PgRow) -> EntryDetail {
+    let share_token: Option<String> = row.get("share_token");
+    let share_url = share_token
+        .as_ref()
+        .map(|token| public_url(state, &format!("/share/{}", token)));
+
+    EntryDetail {
+        id: row.get("id"),
+        created_at: row.get("created_at"),
+        image_url: media_url(state, &row.get::<String, _>("image_path")),
+        label: row.get("label"),
+        description: row.get("description"),
+        confidence: row.get("confidence"),
+        tags: row.get::<Vec<String>, _>("tags"),
+        shared: share_token.is_some(),
+        share_url,
+        model: row.get("model"),
+        lang: row.get("lang"),
+        extra: row.get("extra"),
+        needs_review: row.get("needs_review"),
+        is_pinned: row.get("is_pinned"),
+        reasoning: row.get("reasoning"),
+        alternatives: row.get("alternatives"),
+        edited_at: row.get("edited_at"),
+        source_entry_id: row.get("source_entry_id"),
+        source: row.get("source"),
+        lat: row.get("lat"),
+        lng: row.get("lng"),
+        classification_status: row.get("classification_status"),
+        camera_make: row.get("camera_make"),
+        camera_model: row.get("camera_model"),
+        verified: row.get("verified"),
+        verified_by: row.get("verified_by"),
+        input_tokens: row.get("input_tokens"),
+        output_tokens: row.get("output_tokens"),
+    }
+}
+
+async fn create_entry(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(params): Query<CreateEntryParams>,
+    mut multipart: Multipart,
+) -> Result<Json<CreateEntryResponse>, AppError> {
+    let mut form = UploadForm {
+        force: params.force,
+        ..UploadForm::default()
+    };
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("image") => {
+                form.image_mime = field.content_type().map(|v| v.to_string());
+                form.image_bytes = Some(field.bytes().await?);
+            }
+            Some("model") => {
+                let model = field.text().await?.trim().to_string();
+                form.model = Some(model).filter(|model| !model.is_empty());
+            }
+            Some("lang") => {
+                let value = field.text().await?.trim().to_lowercase();
+                form.lang = Some(value).filter(|value| !value.is_empty());
+            }
+            Some("explain") => {
+                let value = field.text().await?.trim().to_lowercase();
+                form.explain = matches!(value.as_str(), "true" | "1" | "on" | "yes");
+            }
+            Some("hint") => form.hint = Some(field.text().await?),
+            Some("generate_tags") => form.generate_tags = form_flag(&field.text().await?),
+            _ => {}
+        }
+    }
+
+    let deadline = state.create_entry_timeout;
+    let upload = process_upload(&state, &headers, form);
+    match tokio::time::timeout(deadline, upload).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("create_entry timed out after {}s", deadline.as_secs());
+            Err(AppError::gateway_timeout(format!(
+                "Creating the entry took longer than {}s",
+                deadline.as_secs()
+            )))
+        }
+    }
+}
+
+/// Removes files written for an upload unless it completes; covers errors and timeouts alike,
+/// since a timed-out upload future is dropped mid-flight.
+struct UploadCleanup {
+    paths: Vec<PathBuf>,
+}
+
+impl UploadCleanup {
+    fn disarm(mut self) {
+        self.paths.clear();
+    }
+}
+
+impl Drop for UploadCleanup {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            if let Err(err) = std::fs::remove_file(path)
+                && err.kind() != std::io::ErrorKind::NotFound
+            {
+                error!("failed to remove {} after failed upload: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Multipart fields of an upload.
+#[derive(Default)]
+struct UploadForm {
+    image_bytes: Option<Bytes>,
+    image_mime: Option<String>,
+    model: Option<String>,
+    lang: Option<String>,
+    explain: bool,
+    hint: Option<String>,
+    generate_tags: Option<bool>,
+    /// `?force=true`: skip the duplicate check.
+    force: bool,
+}
+
+async fn process_upload(
+    state: &AppState,
+    headers: &HeaderMap,
+    form: UploadForm,
+) -> Result<Json<CreateEntryResponse>, AppError> {
+    let options = classify_options(
+        state,
+        form.model,
+        form.lang,
+        form.explain,
+        form.hint,
+        form.generate_tags,
+    )?;
+
+    let bytes = form
+        .image_bytes
+        .ok_or_else(|| AppError::bad_request("Missing image field"))?;
+    let PreparedImage {
+        bytes,
+        mime,
+        decoded,
+        metadata,
+    } = prepare_image(state, bytes, form.image_mime.as_deref())?;
+
+    let hash = content_hash(&bytes);
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    let lock_key = match &idempotency_key {
+        Some(key) => format!("key:{}", key),
+        None => format!("hash:{}", hash),
+    };
+    let _upload_guard = lock_upload(state, lock_key).await;
+    if idempotency_key.is_some()
+        && let Some(row) = find_retried_entry(state, idempotency_key.as_deref(), &hash).await?
+    {
+        return Ok(Json(CreateEntryResponse {
+            entry: entry_detail_from_row(state, row),
+            duplicate: false,
+        }));
+    }
+    if !form.force && let Some(row) = find_duplicate_entry(state, &hash).await? {
+        return Ok(Json(CreateEntryResponse {
+            entry: entry_detail_from_row(state, row),
+            duplicate: true,
+        }));
+    }
+
+    let entry = store_entry(
+        state,
+        NewEntry {
+            bytes,
+            mime,
+            decoded,
+            metadata,
+            options,
+            content_hash: hash,
+            idempotency_key,
+            source_entry_id: None,
+            classification: EntryClassification::Classify,
+        },
+    )
+    .await?;
+    Ok(Json(CreateEntryResponse {
+        entry,
+        duplicate: false,
+    }))
+}
+
+/// An upload after type detection, decoding and the optional re-encode.
+struct PreparedImage {
+    bytes: Bytes,
+    mime: String,
+    decoded: Option<DynamicImage>,
+    /// Read from the original bytes, before re-encoding or stripping drops the EXIF.
+    metadata: exif::Metadata,
+}
+
+fn prepare_image(
+    state: &AppState,
+    mut bytes: Bytes,
+    declared_mime: Option<&str>,
+) -> Result<PreparedImage, AppError> {
+    let mut mime = detect_image_mime(&bytes, declared_mime)?;
+    let metadata = exif::read(&bytes, &mime);
+
+    // Undecodable uploads are still stored (without thumbnail); oversized ones are refused.
+    let decoded = match decode_image(state, &bytes) {
+        Ok(img) => Some(img),
+        Err(err @ image::ImageError::Limits(_)) => return Err(err.into()),
+        Err(_) => None,
+    };
+    if let (Some(format), Some(img)) = (state.reencode_format, &decoded)
+        && format.to_mime_type() != mime
+    {
+        bytes = Bytes::from(encode_image(img, format)?);
+        mime = format.to_mime_type().to_string();
+    } else if state.strip_exif {
+        bytes = strip_metadata(bytes, &mime, decoded.as_ref())?;
+    }
+    Ok(PreparedImage {
+        bytes,
+        mime,
+        decoded,
+        metadata,
+    })
+}
+
+/// Drops EXIF and other metadata from an upload (`STRIP_EXIF`), losslessly where possible.
+///
+/// Files the container parser can't handle are re-encoded from the decoded pixels instead,
+/// which writes no metadata. GIFs carry none worth removing and are kept as they are.
+fn strip_metadata(bytes: Bytes, mime: &str, decoded: Option<&DynamicImage>) -> Result<Bytes, AppError> {
+    if let Some(stripped) = exif::strip(&bytes, mime) {
+        return Ok(Bytes::from(stripped));
+    }
+    match (ImageFormat::from_mime_type(mime), decoded) {
+        (Some(format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP)), Some(img)) => {
+            Ok(Bytes::from(encode_image(img, format)?))
+        }
+        _ => Ok(bytes),
+    }
+}
+
+/// How a batch upload is classified.
+#[derive(Clone, Copy, PartialEq)]
+enum BatchMode {
+    /// One Anthropic call per image, exactly as `POST /api/entries`.
+    Single,
+    /// Up to `BATCH_IMAGES_PER_CALL` images per call, asking for an array of results.
+    Combined,
+}
+
+/// What a batch upload does with an image whose classification fails (`on_failure`).
+#[derive(Clone, Copy, PartialEq)]
+enum BatchFailurePolicy {
+    /// Leave the image out and carry on.
+    Skip,
+    /// Store it as an `Unclassified` entry with `classification_status: "failed"`.
+    Store,
+    /// Stop, and remove the entries this batch already created.
+    Abort,
+}
+
+#[derive(Serialize)]
+struct BatchUploadResponse {
+    /// Images that now have an entry (`created` or `stored`).
+    created: usize,
+    failed: usize,
+    /// Whether `on_failure=abort` stopped the batch.
+    aborted: bool,
+    /// One result per uploaded image, in upload order.
+    results: Vec<BatchItemResult>,
+}
+
+#[derive(Serialize)]
+struct BatchItemResult {
+    /// 0-based position of the image in the upload.
+    index: usize,
+    status: BatchItemStatus,
+    entry: Option<EntryDetail>,
+    error: Option<String>,
+}
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum BatchItemStatus {
+    Created,
+    /// Classification failed; stored unclassified (`on_failure=store`).
+    Stored,
+    /// Classification failed; left out (`on_failure=skip`).
+    Skipped,
+    /// The image itself was unusable, or storing it failed.
+    Failed,
+    /// Not kept because the batch was aborted.
+    Aborted,
+}
+
+impl BatchItemResult {
+    fn created(index: usize, entry: EntryDetail) -> Self {
+        Self {
+            index,
+            status: BatchItemStatus::Created,
+            entry: Some(entry),
+            error: None,
+        }
+    }
+
+    fn with_error(index: usize, status: BatchItemStatus, err: AppError) -> Self {
+        Self {
+            index,
+            status,
+            entry: None,
+            error: Some(err.message),
+        }
+    }
+
+    fn failed(index: usize, err: AppError) -> Self {
+        Self::with_error(index, BatchItemStatus::Failed, err)
+    }
+}
+
+/// Creates one entry per `image` field; by default a failing image doesn't stop the others.
+///
+/// With `mode=combined`, images are classified several per Anthropic call to save the
+/// per-call overhead; anything the combined answer doesn't cover cleanly is classified
+/// on its own. Each call's images share one `CREATE_ENTRY_TIMEOUT_SECS` deadline.
+/// `on_failure` picks what a classification failure does; see `BatchFailurePolicy`.
+async fn create_entries_batch(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<BatchUploadResponse>, AppError> {
+    let mut images = Vec::new();
+    let (mut model, mut lang, mut explain, mut mode) = (None, None, false, BatchMode::Single);
+    let (mut hint, mut generate_tags) = (None, None);
+    let mut policy = BatchFailurePolicy::Skip;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("image") => {
+                let declared_mime = field.content_type().map(|v| v.to_string());
+                images.push((field.bytes().await?, declared_mime));
+            }
+            Some("model") => {
+                let value = field.text().await?.trim().to_string();
+                model = Some(value).filter(|value| !value.is_empty());
+            }
+            Some("lang") => {
+                let value = field.text().await?.trim().to_lowercase();
+                lang = Some(value).filter(|value| !value.is_empty());
+            }
+            Some("explain") => {
+                let value = field.text().await?.trim().to_lowercase();
+                explain = matches!(value.as_str(), "true" | "1" | "on" | "yes");
+            }
+            Some("hint") => hint = Some(field.text().await?),
+            Some("generate_tags") => generate_tags = form_flag(&field.text().await?),
+            Some("mode") => {
+                mode = match field.text().await?.trim() {
+                    "" | "single" => BatchMode::Single,
+                    "combined" => BatchMode::Combined,
+                    other => {
+                        return Err(AppError::bad_request(format!(
+                            "Unknown mode '{}': expected single or combined",
+                            other
+                        )));
+                    }
+                };
+            }
+            Some("on_failure") => {
+                policy = match field.text().await?.trim() {
+                    "" | "skip" => BatchFailurePolicy::Skip,
+                    "store" => BatchFailurePolicy::Store,
+                    "abort" => BatchFailurePolicy::Abort,
+                    other => {
+                        return Err(AppError::bad_request(format!(
+                            "Unknown on_failure '{}': expected skip, store or abort",
+                            other
+                        )));
+                    }
+                };
+            }
+            _ => {}
+        }
+    }
+    if images.is_empty() {
+        return Err(AppError::bad_request("Missing image field"));
+    }
+    if images.len() > state.batch_max_images {
+        return Err(AppError::bad_request(format!(
+            "At most {} images can be uploaded at once",
+            state.batch_max_images
+        )));
+    }
+    let options = classify_options(&state, model, lang, explain, hint, generate_tags)?;
+
+    let mut results = Vec::with_capacity(images.len());
+    let mut prepared = Vec::with_capacity(images.len());
+    for (index, (bytes, declared_mime)) in images.into_iter().enumerate() {
+        match prepare_image(&state, bytes, declared_mime.as_deref()) {
+            Ok(image) => prepared.push((index, image)),
+            Err(err) => results.push(BatchItemResult::failed(index, err)),
+        }
+    }
+
+    let per_call = match mode {
+        BatchMode::Single => 1,
+        BatchMode::Combined => state.batch_images_per_call,
+    };
+    let deadline = state.create_entry_timeout;
+    let mut created_ids = Vec::new();
+    let mut aborted = false;
+    let mut pending = prepared.into_iter();
+    while !aborted {
+        let chunk: Vec<(usize, PreparedImage)> = pending.by_ref().take(per_call).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let indices: Vec<usize> = chunk.iter().map(|(index, _)| *index).collect();
+        let stored = store_batch_chunk(&state, &options, policy, chunk);
+        match tokio::time::timeout(deadline, stored).await {
+            Ok(outcome) => {
+                results.extend(outcome.results);
+                created_ids.extend(outcome.created_ids);
+                aborted = outcome.aborted;
+            }
+            Err(_) => {
+                warn!("batch upload chunk timed out after {}s", deadline.as_secs());
+                results.extend(indices.into_iter().map(|index| {
+                    BatchItemResult::failed(
+                        index,
+                        AppError::gateway_timeout(format!(
+                            "Creating the entry took longer than {}s",
+                            deadline.as_secs()
+                        )),
+                    )
+                }));
+            }
+        }
+    }
+
+    if aborted {
+        for (index, _) in pending {
+            results.push(BatchItemResult::with_error(
+                index,
+                BatchItemStatus::Aborted,
+                AppError::bad_request("Not processed: the batch was aborted"),
+            ));
+        }
+        // Only entries this batch inserted are removed; ones from earlier uploads of the
+        // same image stay.
+        discard_entries(&state, &created_ids).await?;
+        for result in &mut results {
+            if let Some(entry) = &result.entry
+                && created_ids.contains(&entry.id)
+            {
+                result.status = BatchItemStatus::Aborted;
+                result.entry = None;
+                result.error = Some("Removed: the batch was aborted".to_string());
+            }
+        }
+    }
+    results.sort_by_key(|result| result.index);
+
+    let created = results
+        .iter()
+        .filter(|result| {
+            matches!(result.status, BatchItemStatus::Created | BatchItemStatus::Stored)
+        })
+        .count();
+    Ok(Json(BatchUploadResponse {
+        created,
+        failed: results.len() - created,
+        aborted,
+        results,
+    }))
+}
+
+/// Results of one batch call, plus what the batch needs to undo an abort.
+struct ChunkOutcome {
+    results: Vec<BatchItemResult>,
+    /// Entries this chunk inserted, as opposed to earlier entries it returned.
+    created_ids: Vec<Uuid>,
+    aborted: bool,
+}
+
+/// Moderates, classifies and stores the images of one batch call.
+async fn store_batch_chunk(
+    state: &AppState,
+    options: &ClassifyOptions,
+    policy: BatchFailurePolicy,
+    chunk: Vec<(usize, PreparedImage)>,
+) -> ChunkOutcome {
+    let mut outcome = ChunkOutcome {
+        results: Vec::new(),
+        created_ids: Vec::new(),
+        aborted: false,
+    };
+    let mut candidates = Vec::with_capacity(chunk.len());
+    for (index, image) in chunk {
+        let hash = content_hash(&image.bytes);
+        match find_retried_entry(state, None, &hash).await {
+            Ok(Some(row)) => {
+                outcome
+                    .results
+                    .push(BatchItemResult::created(index, entry_detail_from_row(state, row)));
+                continue;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                outcome.results.push(BatchItemResult::failed(index, err));
+                continue;
+            }
+        }
+        match moderated_send_image(state, &image).await {
+            Ok((send_bytes, send_mime)) => {
+                candidates.push((index, image, hash, send_bytes, send_mime))
+            }
+            Err(err) => outcome.results.push(BatchItemResult::failed(index, err)),
+        }
+    }
+
+    let mut classifications: Vec<Option<Classification>> =
+        candidates.iter().map(|_| None).collect();
+    if candidates.len() > 1 {
+        let images: Vec<(&[u8], &str)> = candidates
+            .iter()
+            .map(|(_, _, _, bytes, mime)| (bytes.as_slice(), mime.as_str()))
+            .collect();
+        match state.classifier.classify_combined(state, &images, options).await {
+            Ok(combined) => classifications = combined,
+            Err(err) => warn!(
+                "combined classification of {} images failed, classifying one by one: {}",
+                images.len(),
+                err.message
+            ),
+        }
+    }
+
+    let mut candidates = candidates.into_iter().zip(classifications);
+    for ((index, image, hash, send_bytes, send_mime), classification) in candidates.by_ref() {
+        let classification = match classification {
+            Some(classification) => EntryClassification::Provided(classification),
+            None => match state.classifier.classify(state, &send_bytes, &send_mime, options).await {
+                Ok(classification) => EntryClassification::Provided(classification),
+                Err(err) => match policy {
+                    BatchFailurePolicy::Skip => {
+                        outcome.results.push(BatchItemResult::with_error(
+                            index,
+                            BatchItemStatus::Skipped,
+                            err,
+                        ));
+                        continue;
+                    }
+                    BatchFailurePolicy::Abort => {
+                        warn!("batch aborted: image {} failed to classify: {}", index, err.message);
+                        outcome.results.push(BatchItemResult::failed(index, err));
+                        outcome.aborted = true;
+                        break;
+                    }
+                    BatchFailurePolicy::Store => {
+                        warn!("storing image {} unclassified: {}", index, err.message);
+                        EntryClassification::Failed
+                    }
+                },
+            },
+        };
+        let unclassified = matches!(classification, EntryClassification::Failed);
+        let stored = store_entry(
+            state,
+            NewEntry {
+                bytes: image.bytes,
+                mime: image.mime,
+                decoded: image.decoded,
+                metadata: image.metadata,
+                options: options.clone(),
+                content_hash: hash,
+                idempotency_key: None,
+                source_entry_id: None,
+                classification,
+            },
+        )
+        .await;
+        outcome.results.push(match stored {
+            Ok(entry) => {
+                outcome.created_ids.push(entry.id);
+                let mut result = BatchItemResult::created(index, entry);
+                if unclassified {
+                    result.status = BatchItemStatus::Stored;
+                }
+                result
+            }
+            Err(err) => BatchItemResult::failed(index, err),
+        });
+    }
+    for ((index, ..), _) in candidates {
+        outcome.results.push(BatchItemResult::with_error(
+            index,
+            BatchItemStatus::Aborted,
+            AppError::bad_request("Not processed: the batch was aborted"),
+        ));
+    }
+    outcome
+}
+
+/// Hard-deletes entries created moments ago, with their files; used to undo an aborted batch.
+async fn discard_entries(state: &AppState, ids: &[Uuid]) -> Result<(), AppError> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let rows = sqlx::query(
+        "WITH discarded AS ( \
+             DELETE FROM entries WHERE id = ANY($1) RETURNING id, image_path, thumb_path \
+         ), tombstones AS ( \
+             INSERT INTO entry_tombstones (id) SELECT id FROM discarded ON CONFLICT DO NOTHING \
+         ) \
+         SELECT * FROM discarded",
+    )
+    .bind(ids)
+    .fetch_all(&state.db)
+    .await?;
+    for row in rows {
+        remove_entry_files(state, row.get("image_path"), row.get("thumb_path")).await;
+    }
+    Ok(())
+}
+
+/// The copy of `image` to send for classification, after it passed moderation if configured.
+async fn moderated_send_image(
+    state: &AppState,
+    image: &PreparedImage,
+) -> Result<(Vec<u8>, String), AppError> {
+    let (send_bytes, send_mime) =
+        anthropic_image(state, &image.bytes, &image.mime, image.decoded.as_ref())?;
+    let (send_bytes, send_mime) = (send_bytes.into_owned(), send_mime.to_string());
+    if let Some(moderation) = &state.moderation {
+        moderate_image(state, moderation, &send_bytes, &send_mime).await?;
+    }
+    Ok((send_bytes, send_mime))
+}
+
+/// Checks the requested model and language against the deployment's configuration.
+fn classify_options(
+    state: &AppState,
+    model: Option<String>,
+    lang: Option<String>,
+    explain: bool,
+    hint: Option<String>,
+    generate_tags: Option<bool>,
+) -> Result<ClassifyOptions, AppError> {
+    let model = match model {
+        Some(model)
+            if model == classification_model(state) || state.model_allowlist.contains(&model) =>
+        {
+            model
+        }
+        Some(model) => {
+            return Err(AppError::bad_request(format!("Model not allowed: {}", model)));
+        }
+        None => classification_model(state).to_string(),
+    };
+    if let Some(lang) = &lang
+        && !is_valid_lang(lang)
+    {
+        return Err(AppError::bad_request(format!("Invalid lang: {}", lang)));
+    }
+    let hint = hint
+        .map(|hint| hint.trim().to_string())
+        .filter(|hint| !hint.is_empty());
+    if hint.as_ref().is_some_and(|hint| hint.chars().count() > MAX_HINT_CHARS) {
+        return Err(AppError::bad_request(format!(
+            "hint must be at most {} characters",
+            MAX_HINT_CHARS
+        )));
+    }
+    Ok(ClassifyOptions {
+        model,
+        lang,
+        explain,
+        hint,
+        generate_tags: generate_tags.unwrap_or(state.generate_tags),
+    })
+}
+
+/// Reads an on/off multipart field; empty or unrecognized values leave the default.
+fn form_flag(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "1" | "on" | "yes" => Some(true),
+        "false" | "0" | "off" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Longest `hint` accepted with an upload.
+const MAX_HINT_CHARS: usize = 200;
+
+/// An image ready to be classified and saved as a new entry.
+struct NewEntry {
+    bytes: Bytes,
+    mime: String,
+    decoded: Option<DynamicImage>,
+    metadata: exif::Metadata,
+    options: ClassifyOptions,
+    content_hash: String,
+    idempotency_key: Option<String>,
+    /// The entry this one was cropped from, for `classify-region`.
+    source_entry_id: Option<Uuid>,
+    classification: EntryClassification,
+}
+
+/// Where a new entry's classification comes from.
+enum EntryClassification {
+    /// Moderate and classify the image in `store_entry`.
+    Classify,
+    /// Already obtained (after moderation) by the batch flow.
+    Provided(Classification),
+    /// Classification failed; store a placeholder marked `failed` for a later retry.
+    Failed,
+}
+
+/// Writes the image, classifies it and inserts the entry; files are removed if any step fails.
+async fn store_entry(state: &AppState, new: NewEntry) -> Result<EntryDetail, AppError> {
+    let NewEntry {
+        bytes,
+        mime,
+        decoded,
+        metadata,
+        options,
+        content_hash,
+        idempotency_key,
+        source_entry_id,
+        classification,
+    } = new;
+    let (width, height) = match &decoded {
+        Some(img) => (Some(img.width() as i32), Some(img.height() as i32)),
+        None => (None, None),
+    };
+
+    let id = Uuid::new_v4();
+    let filename = format!("images/{}.{}", id, image_extension(&mime));
+    let file_path = state.storage_dir.join(&filename);
+    let mut cleanup = UploadCleanup {
+        paths: vec![file_path.clone()],
+    };
+    write_atomic(&file_path, &bytes).await?;
+
+    let (mut classification, classified) = match classification {
+        EntryClassification::Provided(classification) => (classification, true),
+        EntryClassification::Classify => {
+            let (send_bytes, send_mime) = anthropic_image(state, &bytes, &mime, decoded.as_ref())?;
+            if let Some(moderation) = &state.moderation {
+                moderate_image(state, moderation, &send_bytes, send_mime).await?;
+            }
+            (state.classifier.classify(state, &send_bytes, send_mime, &options).await?, true)
+        }
+        EntryClassification::Failed => (Classification::unclassified(), false),
+    };
+    let raw_json = match state.store_raw_json && classified {
+        true => Some(serde_json::to_value(&classification)?),
+        false => None,
+    };
+    apply_classification_rules(state, &options, &mut classification);
+
+    let needs_review = category_mismatch(state, &classification);
+    if needs_review {
+        warn!("entry {} flagged for review: category contradicts its label or tags", id);
+    }
+
+    let thumb_path = match &decoded {
+        Some(img) => match write_thumbnail(state, id, img).await {
+            Ok(path) => {
+                cleanup.paths.push(state.storage_dir.join(&path));
+                Some(path)
+            }
+            Err(err) => {
+                error!("failed to generate thumbnail for {}: {}", id, err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    sqlx::query(
+        "INSERT INTO entries (id, image_path, thumb_path, image_mime, image_width, image_height, label, description, confidence, tags, raw_json, model, content_hash, lang, extra, idempotency_key, needs_review, reasoning, alternatives, source_entry_id, classification_status, camera_make, camera_model, lat, lng, phash, input_tokens, output_tokens) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28)",
+    )
+    .bind(id)
+    .bind(&filename)
+    .bind(&thumb_path)
+    .bind(&mime)
+    .bind(width)
+    .bind(height)
+    .bind(&classification.label)
+    .bind(&classification.description)
+    .bind(classification.confidence)
+    .bind(&classification.tags)
+    .bind(raw_json)
+    .bind(Some(&options.model).filter(|_| classified))
+    .bind(&content_hash)
+    .bind(&options.lang)
+    .bind(serde_json::Value::Object(classification.extra.clone()))
+    .bind(&idempotency_key)
+    .bind(needs_review)
+    .bind(&classification.reasoning)
+    .bind(serde_json::to_value(&classification.alternatives)?)
+    .bind(source_entry_id)
+    .bind(if classified { "classified" } else { "failed" })
+    .bind(&metadata.make)
+    .bind(&metadata.model)
+    .bind(metadata.lat)
+    .bind(metadata.lng)
+    .bind(decoded.as_ref().map(perceptual_hash))
+    .bind(classification.usage.map(|usage| usage.input_tokens))
+    .bind(classification.usage.map(|usage| usage.output_tokens))
+    .execute(&state.db)
+    .await?;
+    cleanup.disarm();
+
+    let auto_share: bool = sqlx::query_scalar("SELECT auto_share FROM settings WHERE id = 1")
+        .fetch_one(&state.db)
+        .await?;
+    if auto_share && let Err(err) = assign_share_token(&state.db, id).await {
+        error!("failed to auto-share entry {}: {}", id, err.message);
+    }
+
+    let row = sqlx::query(&format!("SELECT {} FROM entries WHERE id = $1", ENTRY_COLUMNS))
+        .bind(id)
+        .fetch_one(&state.db)
+        .await?;
+    Ok(entry_detail_from_row(state, row))
+}
+
+/// Description limits, tag normalization and `DEFAULT_TAGS`, applied before a
+/// classification is stored.
+fn apply_classification_rules(
+    state: &AppState,
+    options: &ClassifyOptions,
+    classification: &mut Classification,
+) {
+    classification.description = truncate_description(
+        &classification.description,
+        state.description_max_sentences,
+        state.description_max_chars,
+    );
+    if !options.generate_tags {
+        classification.tags.clear();
+        return;
+    }
+    classification.tags = normalize_tags(&classification.tags, state.collapse_plural_tags);
+    for tag in &state.default_tags {
+        if !classification.tags.contains(tag) {
+            classification.tags.push(tag.clone());
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct ReclassifyPayload {
+    model: Option<String>,
+    lang: Option<String>,
+    #[serde(default)]
+    explain: bool,
+    hint: Option<String>,
+    generate_tags: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct ReclassifyResponse {
+    entry: EntryDetail,
+    /// Whether label, description, tags or confidence came out different.
+    changed: bool,
+    /// The fields that differ, as `{ "before", "after" }`; empty when unchanged.
+    changes: BTreeMap<&'static str, FieldChange>,
+}
+
+#[derive(Serialize)]
+struct FieldChange {
+    before: serde_json::Value,
+    after: serde_json::Value,
+}
+
+/// Runs classification again on a saved entry's image, e.g. after switching models.
+///
+/// The image and `created_at` stay; everything the model produced is replaced, and since
+/// the label may change, a manual edit or reviewer verification is cleared as well.
+async fn reclassify_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    payload: Option<Json<ReclassifyPayload>>,
+) -> Result<Json<ReclassifyResponse>, AppError> {
+    let payload = payload.map(|Json(payload)| payload).unwrap_or_default();
+    let deadline = state.create_entry_timeout;
+    match tokio::time::timeout(deadline, reclassify(&state, id, payload)).await {
+        Ok(result) => result.map(Json),
+        Err(_) => {
+            warn!("reclassify for {} timed out after {}s", id, deadline.as_secs());
+            Err(AppError::gateway_timeout(format!(
+                "Reclassifying the entry took longer than {}s",
+                deadline.as_secs()
+            )))
+        }
+    }
+}
+
+async fn reclassify(
+    state: &AppState,
+    id: Uuid,
+    payload: ReclassifyPayload,
+) -> Result<ReclassifyResponse, AppError> {
+    let options = classify_options(
+        state,
+        payload.model,
+        payload.lang,
+        payload.explain,
+        payload.hint,
+        payload.generate_tags,
+    )?;
+    let row = sqlx::query(
+        "SELECT image_path, image_mime, deleted_at, label, description, tags, confidence \
+         FROM entries WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    if row.get::<Option<DateTime<Utc>>, _>("deleted_at").is_some() {
+        return Err(AppError::bad_request("Deleted entries can't be reclassified"));
+    }
+    let image_path: String = row.get("image_path");
+
+    let bytes = match tokio::fs::read(state.storage_dir.join(&image_path)).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::unprocessable(format!(
+                "The image for entry {} is missing on disk ({})",
+                id, image_path
+            )));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mime = match row.get::<Option<String>, _>("image_mime") {
+        Some(mime) => mime,
+        None => detect_image_mime(&bytes, None).map_err(|err| AppError::unprocessable(err.message))?,
+    };
+    let decoded = decode_image(state, &bytes).ok();
+    let (send_bytes, send_mime) = anthropic_image(state, &bytes, &mime, decoded.as_ref())?;
+    let mut classification = state.classifier.classify(state, &send_bytes, send_mime, &options).await?;
+    let raw_json = match state.store_raw_json {
+        true => Some(serde_json::to_value(&classification)?),
+        false => None,
+    };
+    apply_classification_rules(state, &options, &mut classification);
+    let changes = classification_changes(&row, &classification);
+
+    // Same answer: skip the write so the entry, its `updated_at` and its ETag stay as they are.
+    if changes.is_empty() {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM entries WHERE id = $1 AND deleted_at IS NULL",
+            ENTRY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+        let row = row.ok_or_else(|| AppError::bad_request("Deleted entries can't be reclassified"))?;
+        return Ok(ReclassifyResponse {
+            entry: entry_detail_from_row(state, row),
+            changed: false,
+            changes,
+        });
+    }
+    let needs_review = category_mismatch(state, &classification);
+
+    let row = sqlx::query(&format!(
+        "UPDATE entries SET label = $1, description = $2, confidence = $3, tags = $4, \
+         raw_json = $5, model = $6, lang = $7, extra = $8, needs_review = $9, reasoning = $10, \
+         alternatives = $11, classification_status = 'classified', edited_at = NULL, \
+         verified = FALSE, verified_by = NULL, updated_at = NOW(), \
+         input_tokens = COALESCE(input_tokens + $13, $13, input_tokens), \
+         output_tokens = COALESCE(output_tokens + $14, $14, output_tokens) \
+         WHERE id = $12 AND deleted_at IS NULL RETURNING {}",
+        ENTRY_COLUMNS
+    ))
+    .bind(&classification.label)
+    .bind(&classification.description)
+    .bind(classification.confidence)
+    .bind(&classification.tags)
+    .bind(raw_json)
+    .bind(&options.model)
+    .bind(&options.lang)
+    .bind(serde_json::Value::Object(classification.extra.clone()))
+    .bind(needs_review)
+    .bind(&classification.reasoning)
+    .bind(serde_json::to_value(&classification.alternatives)?)
+    .bind(id)
+    .bind(classification.usage.map(|usage| usage.input_tokens))
+    .bind(classification.usage.map(|usage| usage.output_tokens))
+    .fetch_optional(&state.db)
+    .await?;
+    // Deleted while the model was answering.
+    let row = row.ok_or_else(|| AppError::bad_request("Deleted entries can't be reclassified"))?;
+    Ok(ReclassifyResponse {
+        entry: entry_detail_from_row(state, row),
+        changed: true,
+        changes,
+    })
+}
+
+/// Differences between a stored entry's classification and a new one. Tags compare as a
+/// set, and confidences within 0.001 count as equal so rounding noise isn't a change.
+fn classification_changes(
+    row: &sqlx::postgres::PgRow,
+    classification: &Classification,
+) -> BTreeMap<&'static str, FieldChange> {
+    let mut changes = BTreeMap::new();
+    let mut compare = |field, before: serde_json::Value, after: serde_json::Value, same: bool| {
+        if !same {
+            changes.insert(field, FieldChange { before, after });
+        }
+    };
+
+    let label: String = row.get("label");
+    compare(
+        "label",
+        label.clone().into(),
+        classification.label.clone().into(),
+        label == classification.label,
+    );
+    let description: String = row.get("description");
+    compare(
+        "description",
+        description.clone().into(),
+        classification.description.clone().into(),
+        description == classification.description,
+    );
+    let tags: Vec<String> = row.get("tags");
+    let same_tags = tags.iter().collect::<HashSet<_>>()
+        == classification.tags.iter().collect::<HashSet<_>>();
+    compare("tags", tags.into(), classification.tags.clone().into(), same_tags);
+    let confidence: Option<f64> = row.get("confidence");
+    let same_confidence = match (confidence, classification.confidence) {
+        (Some(before), Some(after)) => (before - after).abs() < 0.001,
+        (before, after) => before.is_none() && after.is_none(),
+    };
+    compare(
+        "confidence",
+        confidence.into(),
+        classification.confidence.into(),
+        same_confidence,
+    );
+    changes
+}
+
+/// Classifies a crop of a saved entry's image as a new entry linked back to it.
+async fn classify_region(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ClassifyRegionPayload>,
+) -> Result<Json<CreateEntryResponse>, AppError> {
+    let deadline = state.create_entry_timeout;
+    match tokio::time::timeout(deadline, crop_and_store(&state, id, payload)).await {
+        Ok(result) => result.map(|entry| {
+            Json(CreateEntryResponse {
+                entry,
+                duplicate: false,
+            })
+        }),
+        Err(_) => {
+            warn!("classify-region for {} timed out after {}s", id, deadline.as_secs());
+            Err(AppError::gateway_timeout(format!(
+                "Creating the entry took longer than {}s",
+                deadline.as_secs()
+            )))
+        }
+    }
+}
+
+async fn crop_and_store(
+    state: &AppState,
+    id: Uuid,
+    payload: ClassifyRegionPayload,
+) -> Result<EntryDetail, AppError> {
+    let options = classify_options(
+        state,
+        payload.model,
+        payload.lang,
+        payload.explain,
+        payload.hint,
+        payload.generate_tags,
+    )?;
+    let row = sqlx::query(
+        "SELECT image_path, image_mime FROM entries WHERE id = $1 AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    let image_path: String = row.get("image_path");
+    let image_mime: String = row.get("image_mime");
+
+    let bytes = match tokio::fs::read(state.storage_dir.join(&image_path)).await {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::unprocessable(format!(
+                "The image for entry {} is missing on disk ({})",
+                id, image_path
+            )));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let img = match decode_image(state, &bytes) {
+        Ok(img) => img,
+        Err(err @ image::ImageError::Limits(_)) => return Err(err.into()),
+        Err(err) => {
+            return Err(AppError::unprocessable(format!(
+                "The image for entry {} could not be decoded: {}",
+                id, err
+            )));
+        }
+    };
+    let (x, y, width, height) = (payload.x, payload.y, payload.width, payload.height);
+    if width == 0
+        || height == 0
+        || x.checked_add(width).is_none_or(|right| right > img.width())
+        || y.checked_add(height).is_none_or(|bottom| bottom > img.height())
+    {
+        return Err(AppError::bad_request(format!(
+            "Crop must be a non-empty box inside the {}x{} image",
+            img.width(),
+            img.height()
+        )));
+    }
+    let cropped = img.crop_imm(x, y, width, height);
+
+    // Keep the source's format when we can encode it, so crops look like uploads.
+    let format = state.reencode_format.unwrap_or_else(|| {
+        ImageFormat::from_mime_type(&image_mime)
+            .filter(|format| ENCODE_FORMATS.iter().any(|(_, known)| known == format))
+            .unwrap_or(ImageFormat::Jpeg)
+    });
+    let encoded = Bytes::from(encode_image(&cropped, format)?);
+
+    store_entry(
+        state,
+        NewEntry {
+            content_hash: content_hash(&encoded),
+            bytes: encoded,
+            mime: format.to_mime_type().to_string(),
+            decoded: Some(cropped),
+            metadata: exif::Metadata::default(),
+            options,
+            idempotency_key: None,
+            source_entry_id: Some(id),
+            classification: EntryClassification::Classify,
+        },
+    )
+    .await
+}
+
+/// Most observations one iNaturalist import may contain.
+const MAX_IMPORT_ROWS: usize = 500;
+
+/// Largest image an import downloads per observation.
+const MAX_IMPORT_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// How long one observation's image download may take.
+const IMPORT_DOWNLOAD_TIMEOUT_SECS: u64 = 30;
+
+#[derive(Serialize)]
+struct ImportResponse {
+    imported: usize,
+    skipped: usize,
+    failed: usize,
+    rows: Vec<ImportRowResult>,
+}
+
+#[derive(Serialize)]
+struct ImportRowResult {
+    /// 1-based position among the data rows, not counting the header.
+    row: usize,
+    status: ImportRowStatus,
+    entry_id: Option<Uuid>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ImportRowStatus {
+    Imported,
+    Skipped,
+    Failed,
+}
+
+/// Creates entries from an iNaturalist observations CSV export (multipart field `file`).
+///
+/// Observations keep their own identification instead of being classified again; rows
+/// without an image are skipped, and one bad row doesn't stop the rest.
+async fn import_inaturalist(
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Result<Json<ImportResponse>, AppError> {
+    let mut csv_text = None;
+    while let Some(field) = multipart.next_field().await? {
+        if field.name() == Some("file") {
+            csv_text = Some(field.text().await?);
+        }
+    }
+    let csv_text = csv_text.ok_or_else(|| AppError::bad_request("Missing file field"))?;
+    let mut records = csv::parse(&csv_text)
+        .map_err(|err| AppError::bad_request(format!("Invalid CSV: {}", err)))?
+        .into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| AppError::bad_request("CSV is empty"))?;
+    let records: Vec<Vec<String>> = records.collect();
+    if records.len() > MAX_IMPORT_ROWS {
+        return Err(AppError::bad_request(format!(
+            "At most {} observations can be imported at once",
+            MAX_IMPORT_ROWS
+        )));
+    }
+
+    let columns: HashMap<String, usize> = header
+        .iter()
+        .enumerate()
+        .map(|(index, name)| (name.trim().to_lowercase(), index))
+        .collect();
+    if !columns.contains_key("image_url") {
+        return Err(AppError::bad_request("CSV has no image_url column"));
+    }
+
+    let mut response = ImportResponse {
+        imported: 0,
+        skipped: 0,
+        failed: 0,
+        rows: Vec::with_capacity(records.len()),
+    };
+    for (index, record) in records.iter().enumerate() {
+        let observation = Observation {
+            columns: &columns,
+            record,
+        };
+        let row = index + 1;
+        let result = match import_observation(&state, &observation).await {
+            Ok(Some(entry_id)) => {
+                response.imported += 1;
+                ImportRowResult {
+                    row,
+                    status: ImportRowStatus::Imported,
+                    entry_id: Some(entry_id),
+                    error: None,
+                }
+            }
+            Ok(None) => {
+                response.skipped += 1;
+                ImportRowResult {
+                    row,
+                    status: ImportRowStatus::Skipped,
+                    entry_id: None,
+                    error: Some("Observation has no image".to_string()),
+                }
+            }
+            Err(err) => {
+                warn!("iNaturalist import row {} failed: {}", row, err.message);
+                response.failed += 1;
+                ImportRowResult {
+                    row,
+                    status: ImportRowStatus::Failed,
+                    entry_id: None,
+                    error: Some(err.message),
+                }
+            }
+        };
+        response.rows.push(result);
+    }
+    info!(
+        "iNaturalist import: {} imported, {} skipped, {} failed",
+        response.imported, response.skipped, response.failed
+    );
+    Ok(Json(response))
+}
+
+/// One data row of an iNaturalist export, looked up by column name.
+struct Observation<'a> {
+    columns: &'a HashMap<String, usize>,
+    record: &'a [String],
+}
+
+impl Observation<'_> {
+    /// The trimmed value of `column`, or `None` when the column is absent or blank.
+    fn get(&self, column: &str) -> Option<&str> {
+        let index = *self.columns.get(column)?;
+        self.record
+            .get(index)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// When the observation was made: the full timestamp if present, else the day at midnight UTC.
+    fn observed_at(&self) -> Option<DateTime<Utc>> {
+        if let Some(value) = self.get("time_observed_at")
+            && let Ok(time) = DateTime::parse_from_rfc3339(value)
+        {
+            return Some(time.with_timezone(&Utc));
+        }
+        let day = chrono::NaiveDate::parse_from_str(self.get("observed_on")?, "%Y-%m-%d").ok()?;
+        Some(day.and_hms_opt(0, 0, 0)?.and_utc())
+    }
+
+    fn coordinate(&self, column: &str, max: f64) -> Option<f64> {
+        self.get(column)?
+            .parse::<f64>()
+            .ok()
+            .filter(|value| value.abs() <= max)
+    }
+}
+
+/// Downloads an observation's image and inserts it; `None` when there is no image to import.
+async fn import_observation(
+    state: &AppState,
+    observation: &Observation<'_>,
+) -> Result<Option<Uuid>, AppError> {
+    // Exports list one URL per observation, but tolerate several separated by commas.
+    let Some(image_url) = observation
+        .get("image_url")
+        .and_then(|urls| urls.split(',').map(str::trim).find(|url| !url.is_empty()))
+    else {
+        return Ok(None);
+    };
+    let (bytes, declared_mime) = download_image(state, image_url).await?;
+    // Same pipeline as uploads, so `STRIP_EXIF` and `IMAGE_REENCODE_FORMAT` apply to imports too.
+    let PreparedImage {
+        bytes,
+        mime,
+        decoded,
+        metadata,
+    } = prepare_image(state, bytes, declared_mime.as_deref())?;
+    let (width, height) = match &decoded {
+        Some(img) => (Some(img.width() as i32), Some(img.height() as i32)),
+        None => (None, None),
+    };
+
+    let label = ["common_name", "species_guess", "scientific_name"]
+        .iter()
+        .find_map(|column| observation.get(column))
+        .unwrap_or("Unknown");
+    let description = observation.get("description").unwrap_or("");
+    let mut tags: Vec<String> = observation
+        .get("iconic_taxon_name")
+        .map(|taxon| vec![taxon.to_lowercase()])
+        .unwrap_or_default();
+    for tag in &state.default_tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    let mut extra = serde_json::Map::new();
+    for (column, key) in [
+        ("scientific_name", "scientific_name"),
+        ("place_guess", "place"),
+        ("id", "inaturalist_id"),
+        ("url", "inaturalist_url"),
+    ] {
+        if let Some(value) = observation.get(column) {
+            extra.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+        }
+    }
+
+    let id = Uuid::new_v4();
+    let filename = format!("images/{}.{}", id, image_extension(&mime));
+    let file_path = state.storage_dir.join(&filename);
+    let mut cleanup = UploadCleanup {
+        paths: vec![file_path.clone()],
+    };
+    write_atomic(&file_path, &bytes).await?;
+    let thumb_path = match &decoded {
+        Some(img) => match write_thumbnail(state, id, img).await {
+            Ok(path) => {
+                cleanup.paths.push(state.storage_dir.join(&path));
+                Some(path)
+            }
+            Err(err) => {
+                error!("failed to generate thumbnail for {}: {}", id, err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    sqlx::query(
+        "INSERT INTO entries (id, created_at, image_path, thumb_path, image_mime, image_width, image_height, label, description, tags, content_hash, extra, source, lat, lng, camera_make, camera_model) \
+         VALUES ($1, COALESCE($2, NOW()), $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, 'inaturalist', $13, $14, $15, $16)",
+    )
+    .bind(id)
+    .bind(observation.observed_at())
+    .bind(&filename)
+    .bind(&thumb_path)
+    .bind(&mime)
+    .bind(width)
+    .bind(height)
+    .bind(label)
+    .bind(description)
+    .bind(&tags)
+    .bind(content_hash(&bytes))
+    .bind(serde_json::Value::Object(extra))
+    .bind(observation.coordinate("latitude", 90.0).or(metadata.lat))
+    .bind(observation.coordinate("longitude", 180.0).or(metadata.lng))
+    .bind(&metadata.make)
+    .bind(&metadata.model)
+    .execute(&state.db)
+    .await?;
+    cleanup.disarm();
+    Ok(Some(id))
+}
+
+/// Fetches `url`, refusing bodies over `MAX_IMPORT_IMAGE_BYTES`; returns the bytes and declared type.
+///
+/// The URL comes from an uploaded CSV, so only http(s) to public addresses is allowed.
+async fn download_image(
+    state: &AppState,
+    url: &str,
+) -> Result<(Bytes, Option<String>), AppError> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| AppError::bad_request(format!("Invalid image URL {}: {}", url, e)))?;
+    check_import_url(&parsed)
+        .map_err(|reason| AppError::bad_request(format!("Refusing to download {}: {}", url, reason)))?;
+    let mut res = state
+        .import_http
+        .get(parsed)
+        .send()
+        .await
+        .map_err(|e| AppError::upstream(format!("Failed to download {}: {}", url, e)))?;
+    if !res.status().is_success() {
+        return Err(AppError::upstream(format!(
+            "Downloading {} returned {}",
+            url,
+            res.status()
+        )));
+    }
+    let declared_mime = res
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.split(';').next().unwrap_or("").trim().to_string());
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .map_err(|e| AppError::upstream(format!("Failed to download {}: {}", url, e)))?
+    {
+        if bytes.len() + chunk.len() > MAX_IMPORT_IMAGE_BYTES {
+            return Err(AppError::payload_too_large(format!(
+                "Image at {} is larger than {} MB",
+                url,
+                MAX_IMPORT_IMAGE_BYTES / (1024 * 1024)
+            )));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok((Bytes::from(bytes), declared_mime))
+}
+
+/// The client behind `download_image`. Hosts resolve to public addresses only, and each
+/// redirect is checked like the original URL, so an import can't reach `localhost`, the
+/// LAN or a cloud metadata endpoint, even through a redirect or a changing DNS answer.
+fn import_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(IMPORT_DOWNLOAD_TIMEOUT_SECS))
+        .connect_timeout(std::time::Duration::from_secs(HTTP_CONNECT_TIMEOUT_SECS))
+        .dns_resolver(Arc::new(PublicOnlyResolver))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= IMPORT_MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match check_import_url(attempt.url()) {
+                Ok(()) => attempt.follow(),
+                Err(reason) => attempt.error(reason),
+            }
+        }))
+        .build()
+}
+
+const IMPORT_MAX_REDIRECTS: usize = 5;
+
+/// Rejects anything but http(s), and hosts given as a non-public IP address; host names
+/// are checked when `PublicOnlyResolver` resolves them.
+fn check_import_url(url: &reqwest::Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("only http and https URLs are allowed, not {}", url.scheme()));
+    }
+    let host = url.host_str().ok_or_else(|| "the URL has no host".to_string())?;
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<std::net::IpAddr>() {
+        Ok(ip) if !is_public_ip(ip) => Err(format!("{} is not a public address", ip)),
+        _ => Ok(()),
+    }
+}
+
+/// Not loopback, private, link-local, shared (CGNAT), documentation, multicast or otherwise
+/// reserved; IPv4-mapped IPv6 addresses are judged as IPv4.
+fn is_public_ip(ip: std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 192 && b == 0 && c == 0)
+                || (a == 198 && (18..20).contains(&b)))
+        }
+        std::net::IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_ip(v4.into());
+            }
+            let first = ip.segments()[0];
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+/// Resolves like the system resolver but drops non-public addresses, failing when none
+/// are left. Checking at connect time means a host can't pass a check and then resolve
+/// elsewhere.
+struct PublicOnlyResolver;
+
+impl reqwest::dns::Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// How long an identical upload without an `Idempotency-Key` is treated as a retry.
+const UPLOAD_RETRY_WINDOW_SECS: i64 = 300;
+
+/// Holds the per-key upload lock; drops the map's entry once nobody else is waiting on it.
+struct UploadGuard {
+    locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
+    key: String,
+    guard: tokio::sync::OwnedMutexGuard<()>,
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock().unwrap();
+        // One reference is the map's, one is ours.
+        if Arc::strong_count(tokio::sync::OwnedMutexGuard::mutex(&self.guard)) <= 2 {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+/// Serializes concurrent uploads of the same image or idempotency key.
+async fn lock_upload(state: &AppState, key: String) -> UploadGuard {
+    let lock = {
+        let mut locks = state.upload_locks.lock().unwrap();
+        locks.entry(key.clone()).or_default().clone()
+    };
+    UploadGuard {
+        locks: state.upload_locks.clone(),
+        key,
+        guard: lock.lock_owned().await,
+    }
+}
+
+/// The newest live entry whose image has the SHA-256 `hash`, however old it is.
+async fn find_duplicate_entry(
+    state: &AppState,
+    hash: &str,
+) -> Result<Option<sqlx::postgres::PgRow>, AppError> {
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM entries WHERE content_hash = $1 AND deleted_at IS NULL \
+         ORDER BY created_at DESC LIMIT 1",
+        ENTRY_COLUMNS
+    ))
+    .bind(hash)
+    .fetch_optional(&state.db)
+    .await?;
+    Ok(row)
+}
+
+/// Looks for the entry a retried upload already produced.
+///
+/// With an `Idempotency-Key` the key decides; otherwise identical bytes uploaded within
+/// the retry window count as the same upload.
+async fn find_retried_entry(
+    state: &AppState,
+    idempotency_key: Option<&str>,
+    hash: &str,
+) -> Result<Option<sqlx::postgres::PgRow>, AppError> {
+    let row = match idempotency_key {
+        Some(key) => {
+            sqlx::query(&format!(
+                "SELECT {} FROM entries WHERE idempotency_key = $1 AND deleted_at IS NULL \
+                 ORDER BY created_at DESC LIMIT 1",
+                ENTRY_COLUMNS
+            ))
+            .bind(key)
+            .fetch_optional(&state.db)
+            .await?
+        }
+        None => {
+            sqlx::query(&format!(
+                "SELECT {} FROM entries WHERE content_hash = $1 AND deleted_at IS NULL \
+                 AND created_at > NOW() - make_interval(secs => $2) \
+                 ORDER BY created_at DESC LIMIT 1",
+                ENTRY_COLUMNS
+            ))
+            .bind(hash)
+            .bind(UPLOAD_RETRY_WINDOW_SECS as f64)
+            .fetch_optional(&state.db)
+            .await?
+        }
+    };
+    Ok(row)
+}
+
+/// Writes to a temporary sibling and renames it into place, so a partial file is never served.
+async fn write_atomic(path: &std::path::Path, bytes: &[u8]) -> Result<(), AppError> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| AppError::internal("Invalid file path"))?;
+    let tmp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, Uuid::new_v4()));
+    if let Err(err) = tokio::fs::write(&tmp_path, bytes).await {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(err.into());
+    }
+    if let Err(err) = tokio::fs::rename(&tmp_path, path).await {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// File extension stored images get for `mime`.
+fn image_extension(mime: &str) -> &'static str {
+    match mime {
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "jpg",
+    }
+}
+
+/// Picks the upload's mime type from its magic bytes, refusing anything but JPEG, PNG, WebP or GIF.
+///
+/// Pasted images often arrive as `application/octet-stream` with no filename, so the
+/// content itself is the only reliable signal. The declared type only goes into the error:
+/// trusting it would store e.g. HEIC or SVG under `.jpg` and send it to the model as such.
+fn detect_image_mime(bytes: &[u8], declared: Option<&str>) -> Result<String, AppError> {
+    if let Ok(format) = image::guess_format(bytes)
+        && matches!(
+            format,
+            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif
+        )
+    {
+        return Ok(format.to_mime_type().to_string());
+    }
+
+    Err(AppError::bad_request(match declared {
+        Some(mime) => format!("Unsupported image format ({}); use JPEG, PNG, WebP or GIF", mime),
+        None => "Unsupported image format; use JPEG, PNG, WebP or GIF".to_string(),
+    }))
+}
+
+/// Shortens an overlong description to whole sentences within the configured caps.
+///
+/// The untouched text stays in `raw_json`. A first sentence that alone exceeds
+/// `max_chars` is cut at a word boundary and ends with an ellipsis.
+fn truncate_description(text: &str, max_sentences: Option<usize>, max_chars: Option<usize>) -> String {
+    let text = text.trim();
+    if max_sentences.is_none() && max_chars.is_none() {
+        return text.to_string();
+    }
+
+    let mut sentence_ends = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, ch)) = chars.next() {
+        if matches!(ch, '.' | '!' | '?')
+            && chars.peek().is_none_or(|(_, next)| next.is_whitespace())
+        {
+            sentence_ends.push(index + ch.len_utf8());
+        }
+    }
+    if sentence_ends.last() != Some(&text.len()) {
+        sentence_ends.push(text.len());
+    }
+
+    let max_chars = max_chars.unwrap_or(usize::MAX);
+    let mut end = 0;
+    for (count, sentence_end) in sentence_ends.into_iter().enumerate() {
+        let within_sentences = max_sentences.is_none_or(|max| count < max);
+        if !within_sentences || text[..sentence_end].chars().count() > max_chars {
+            break;
+        }
+        end = sentence_end;
+    }
+
+    if end > 0 {
+        return text[..end].to_string();
+    }
+
+    let cut: String = text.chars().take(max_chars).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(space) => &cut[..space],
+        None => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_whitespace() || c == ','))
+}
+
+fn parse_image_format(var: &str, value: &str, default: &str) -> anyhow::Result<ImageFormat> {
+    let name = value.trim().to_ascii_lowercase();
+    ENCODE_FORMATS
+        .iter()
+        .find(|(candidate, format)| *candidate == name && format.writing_enabled())
+        .map(|(_, format)| *format)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unsupported {} '{}': expected one of jpeg, png, webp (default: {})",
+                var,
+                value,
+                default
+            )
+        })
+}
+
+/// Decodes an image within `IMAGE_MAX_DIMENSION` / `IMAGE_MAX_DECODE_MB`, so a small
+/// file that claims huge dimensions fails with `ImageError::Limits` instead of exhausting memory.
+fn decode_image(state: &AppState, bytes: &[u8]) -> Result<DynamicImage, image::ImageError> {
+    let mut limits = image::Limits::default();
+    limits.max_image_width = Some(state.image_max_dimension);
+    limits.max_image_height = Some(state.image_max_dimension);
+    limits.max_alloc = Some(state.image_max_decode_bytes);
+
+    let mut reader = image::ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+    reader.limits(limits);
+    reader.decode()
+}
+
+fn encode_image(img: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, AppError> {
+    // JPEG has no alpha channel; the other formats keep it.
+    let img = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()),
+        _ => DynamicImage::ImageRgba8(img.to_rgba8()),
+    };
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, format)?;
+    Ok(out.into_inner())
+}
+
+fn encode_thumbnail(img: &DynamicImage, max_dim: u32, format: ImageFormat) -> Result<Vec<u8>, AppError> {
+    encode_image(&img.thumbnail(max_dim, max_dim), format)
+}
+
+/// Writes `images/{id}_thumb.{ext}` using the configured size and format.
+async fn write_thumbnail(state: &AppState, id: Uuid, img: &DynamicImage) -> Result<String, AppError> {
+    let bytes = encode_thumbnail(img, state.thumb_max_dim, state.thumb_format)?;
+    let extension = state.thumb_format.extensions_str()[0];
+    let thumb_path = format!("images/{}_thumb.{}", id, extension);
+    write_atomic(&state.storage_dir.join(&thumb_path), &bytes).await?;
+    Ok(thumb_path)
+}
+
+/// Writes a fresh thumbnail for one entry and returns its path.
+async fn regenerate_thumbnail(state: &AppState, id: Uuid, image_path: &str) -> Result<String, AppError> {
+    let bytes = tokio::fs::read(state.storage_dir.join(image_path)).await?;
+    let img = decode_image(state, &bytes)?;
+    let thumb_path = write_thumbnail(state, id, &img).await?;
+
+    sqlx::query("UPDATE entries SET thumb_path = $1, phash = $2, updated_at = NOW() WHERE id = $3")
+        .bind(&thumb_path)
+        .bind(perceptual_hash(&img))
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(thumb_path)
+}
+
+/// Re-encodes one stored original to `format`, then swaps it in and refreshes its thumbnail.
+async fn reprocess_image(
+    state: &AppState,
+    id: Uuid,
+    image_path: &str,
+    format: ImageFormat,
+) -> Result<(), AppError> {
+    let bytes = tokio::fs::read(state.storage_dir.join(image_path)).await?;
+    let img = decode_image(state, &bytes)?;
+    let encoded = encode_image(&img, format)?;
+    let new_path = format!("images/{}.{}", id, format.extensions_str()[0]);
+    write_atomic(&state.storage_dir.join(&new_path), &encoded).await?;
+
+    sqlx::query(
+        "UPDATE entries SET image_path = $1, image_mime = $2, image_width = $3, image_height = $4, \
+         content_hash = $5, updated_at = NOW() WHERE id = $6",
+    )
+    .bind(&new_path)
+    .bind(format.to_mime_type())
+    .bind(img.width() as i32)
+    .bind(img.height() as i32)
+    .bind(content_hash(&encoded))
+    .bind(id)
+    .execute(&state.db)
+    .await?;
+
+    if new_path != image_path
+        && let Err(err) = tokio::fs::remove_file(state.storage_dir.join(image_path)).await
+    {
+        error!("failed to remove old image {}: {}", image_path, err);
+    }
+
+    let thumb_path = write_thumbnail(state, id, &img).await?;
+    sqlx::query("UPDATE entries SET thumb_path = $1, updated_at = NOW() WHERE id = $2")
+        .bind(&thumb_path)
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(())
+}
+
+async fn reprocess_images(
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<JobStatus>), AppError> {
+    let format = state
+        .reencode_format
+        .ok_or_else(|| AppError::bad_request("IMAGE_REENCODE_FORMAT is not set"))?;
+
+    let rows = sqlx::query(
+        "SELECT id, image_path FROM entries WHERE image_mime <> $1 AND deleted_at IS NULL",
+    )
+    .bind(format.to_mime_type())
+    .fetch_all(&state.db)
+    .await?;
+    let targets: Vec<(Uuid, String)> = rows
+        .into_iter()
+        .map(|row| (row.get("id"), row.get("image_path")))
+        .collect();
+
+    let job = start_job(&state, "image_reprocess", targets.len());
+    let job_id = job.id;
+    tokio::spawn(async move {
+        for (id, image_path) in targets {
+            let result = reprocess_image(&state, id, &image_path, format).await;
+            if let Err(err) = &result {
+                error!("failed to reprocess image for {}: {}", id, err);
+            }
+            update_job(&state, job_id, |job| {
+                job.processed += 1;
+                if result.is_err() {
+                    job.failed += 1;
+                }
+            });
+        }
+        finish_job(&state, job_id, None);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Hex-encoded SHA-256 of a stored image file.
+fn content_hash(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// 64-bit difference hash: each bit says whether a pixel of a 9x8 grayscale thumbnail is
+/// brighter than its right neighbour. Visually similar images differ in few bits, so the
+/// Hamming distance between two hashes ranks similarity; it survives resizing and
+/// re-encoding, though not crops or rotation.
+fn perceptual_hash(img: &DynamicImage) -> i64 {
+    let small = img
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash as i64
+}
+
+/// Stores the content and perceptual hashes of one entry's image and returns other live
+/// entries with the same content hash. An image that won't decode keeps no perceptual hash.
+async fn backfill_content_hash(
+    state: &AppState,
+    id: Uuid,
+    image_path: &str,
+) -> Result<Vec<Uuid>, AppError> {
+    let bytes = tokio::fs::read(state.storage_dir.join(image_path)).await?;
+    let hash = content_hash(&bytes);
+    let phash = decode_image(state, &bytes).ok().map(|img| perceptual_hash(&img));
+
+    sqlx::query("UPDATE entries SET content_hash = $1, phash = COALESCE($2, phash) WHERE id = $3")
+        .bind(&hash)
+        .bind(phash)
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    let duplicates = sqlx::query_scalar(
+        "SELECT id FROM entries WHERE content_hash = $1 AND id <> $2 AND deleted_at IS NULL",
+    )
+    .bind(&hash)
+    .bind(id)
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(duplicates)
+}
+
+async fn backfill_hashes(
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<JobStatus>), AppError> {
+    let rows = sqlx::query(
+        "SELECT id, image_path FROM entries \
+         WHERE (content_hash IS NULL OR phash IS NULL) AND deleted_at IS NULL",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let targets: Vec<(Uuid, String)> = rows
+        .into_iter()
+        .map(|row| (row.get("id"), row.get("image_path")))
+        .collect();
+
+    let job = start_job(&state, "hash_backfill", targets.len());
+    let job_id = job.id;
+    tokio::spawn(async move {
+        for (id, image_path) in targets {
+            let result = backfill_content_hash(&state, id, &image_path).await;
+            if let Err(err) = &result {
+                error!("failed to hash image for {}: {}", id, err);
+            }
+            update_job(&state, job_id, |job| {
+                job.processed += 1;
+                match &result {
+                    Ok(duplicates) if !duplicates.is_empty() => {
+                        job.findings.push(serde_json::json!({
+                            "entry_id": id,
+                            "duplicate_of": duplicates,
+                        }));
+                    }
+                    Ok(_) => {}
+                    Err(_) => job.failed += 1,
+                }
+            });
+        }
+        finish_job(&state, job_id, None);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+async fn list_missing_thumbnails(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let ids: Vec<Uuid> = sqlx::query_scalar(
+        "SELECT id FROM entries WHERE thumb_path IS NULL AND deleted_at IS NULL ORDER BY created_at DESC",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    Ok(Json(serde_json::json!({ "count": ids.len(), "ids": ids })))
+}
+
+async fn backfill_thumbnails(
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<JobStatus>), AppError> {
+    let rows = sqlx::query(
+        "SELECT id, image_path FROM entries WHERE thumb_path IS NULL AND deleted_at IS NULL",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let targets: Vec<(Uuid, String)> = rows
+        .into_iter()
+        .map(|row| (row.get("id"), row.get("image_path")))
+        .collect();
+
+    let job = start_job(&state, "thumbnail_backfill", targets.len());
+    let job_id = job.id;
+    tokio::spawn(async move {
+        for (id, image_path) in targets {
+            let result = regenerate_thumbnail(&state, id, &image_path).await;
+            if let Err(err) = &result {
+                error!("failed to backfill thumbnail for {}: {}", id, err);
+            }
+            update_job(&state, job_id, |job| {
+                job.processed += 1;
+                if result.is_err() {
+                    job.failed += 1;
+                }
+            });
+        }
+        finish_job(&state, job_id, None);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// How many thumbnails `regenerate_thumbnails` works on at once.
+const THUMBNAIL_REGENERATE_CONCURRENCY: usize = 4;
+
+/// Rewrites every entry's thumbnail with the current `THUMB_MAX_DIM` / `THUMB_FORMAT`,
+/// removing the old file when the format (and so the file name) changed.
+async fn regenerate_thumbnails(
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<JobStatus>), AppError> {
+    let rows = sqlx::query(
+        "SELECT id, image_path, thumb_path FROM entries WHERE deleted_at IS NULL ORDER BY created_at DESC",
+    )
+    .fetch_all(&state.db)
+    .await?;
+    let targets: Vec<(Uuid, String, Option<String>)> = rows
+        .into_iter()
+        .map(|row| (row.get("id"), row.get("image_path"), row.get("thumb_path")))
+        .collect();
+
+    let job = start_job(&state, "thumbnail_regenerate", targets.len());
+    let job_id = job.id;
+    tokio::spawn(async move {
+        let state = &state;
+        futures_util::stream::iter(targets)
+            .map(|(id, image_path, old_thumb)| async move {
+                let result = regenerate_thumbnail(state, id, &image_path).await;
+                match &result {
+                    Ok(thumb_path) => {
+                        if let Some(old_thumb) = old_thumb.filter(|old| old != thumb_path)
+                            && let Err(err) = tokio::fs::remove_file(state.storage_dir.join(&old_thumb)).await
+                            && err.kind() != std::io::ErrorKind::NotFound
+                        {
+                            error!("failed to remove old thumbnail {}: {}", old_thumb, err);
+                        }
+                    }
+                    Err(err) => error!("failed to regenerate thumbnail for {}: {}", id, err),
+                }
+                update_job(state, job_id, |job| {
+                    job.processed += 1;
+                    if result.is_err() {
+                        job.failed += 1;
+                    }
+                });
+            })
+            .buffer_unordered(THUMBNAIL_REGENERATE_CONCURRENCY)
+            .collect::<()>()
+            .await;
+        finish_job(state, job_id, None);
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+fn start_job(state: &AppState, kind: &'static str, total: usize) -> JobStatus {
+    let job = JobStatus {
+        id: Uuid::new_v4(),
+        kind,
+        state: JobState::Running,
+        total,
+        processed: 0,
+        failed: 0,
+        started_at: Utc::now(),
+        finished_at: None,
+        error: None,
+        findings: Vec::new(),
+    };
+    state.jobs.lock().unwrap().insert(job.id, job.clone());
+    job
+}
+
+fn update_job(state: &AppState, id: Uuid, update: impl FnOnce(&mut JobStatus)) {
+    if let Some(job) = state.jobs.lock().unwrap().get_mut(&id) {
+        update(job);
+    }
+}
+
+fn finish_job(state: &AppState, id: Uuid, error: Option<String>) {
+    update_job(state, id, |job| {
+        job.state = if error.is_some() {
+            JobState::Failed
+        } else {
+            JobState::Completed
+        };
+        job.finished_at = Some(Utc::now());
+        job.error = error;
+    });
+}
+
+async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobStatus>, AppError> {
+    let job = state.jobs.lock().unwrap().get(&id).cloned();
+    job.map(Json)
+        .ok_or_else(|| AppError::not_found("Job not found"))
+}
+
+async fn soft_delete_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = sqlx::query(
+        "UPDATE entries SET deleted_at = NOW(), delete_reason = 'user', updated_at = NOW() \
+         WHERE id = $1 AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .execute(&state.db)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::not_found("Entry not found"));
+    }
+
+    notify_webhook(&state, "entry.deleted", id, "user");
+
+    Ok(Json(serde_json::json!({ "status": "deleted" })))
+}
+
+#[derive(Deserialize)]
+struct DeleteByTagPayload {
+    tag: String,
+    /// Must repeat `tag`, so a bulk delete can't happen by accident.
+    confirm: String,
+}
+
+/// Soft-deletes every entry with the tag (or one of its synonyms), restorable as usual.
+async fn delete_entries_by_tag(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<DeleteByTagPayload>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let tag = payload.tag.trim().to_lowercase();
+    if tag.is_empty() {
+        return Err(AppError::bad_request("tag must not be empty"));
+    }
+    if payload.confirm.trim().to_lowercase() != tag {
+        return Err(AppError::bad_request("confirm must repeat the tag being deleted"));
+    }
+
+    let mut tx = state.db.begin().await?;
+    let ids: Vec<Uuid> = sqlx::query_scalar(
+        "UPDATE entries SET deleted_at = NOW(), delete_reason = 'user', updated_at = NOW() \
+         WHERE tags && $1::text[] AND deleted_at IS NULL RETURNING id",
+    )
+    .bind(expand_tag_synonyms(&state, &tag))
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    info!("deleted {} entries tagged {}", ids.len(), tag);
+    for id in &ids {
+        notify_webhook(&state, "entry.deleted", *id, "user");
+    }
+    Ok(Json(serde_json::json!({ "status": "deleted", "deleted": ids.len() })))
+}
+
+/// Upper bound on ids accepted by `bulk_delete_entries`.
+const MAX_BULK_DELETE_IDS: usize = 500;
+
+#[derive(Deserialize)]
+struct BulkDeletePayload {
+    ids: Vec<Uuid>,
+}
+
+/// Soft-deletes the given entries in one statement, restorable as usual. Ids that don't
+/// exist or are already deleted are skipped, so `deleted` can be less than asked for.
+async fn bulk_delete_entries(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<BulkDeletePayload>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if payload.ids.len() > MAX_BULK_DELETE_IDS {
+        return Err(AppError::bad_request(format!(
+            "At most {} ids can be deleted at once",
+            MAX_BULK_DELETE_IDS
+        )));
+    }
+
+    let ids: Vec<Uuid> = sqlx::query_scalar(
+        "UPDATE entries SET deleted_at = NOW(), delete_reason = 'user', updated_at = NOW() \
+         WHERE id = ANY($1) AND deleted_at IS NULL RETURNING id",
+    )
+    .bind(&payload.ids)
+    .fetch_all(&state.db)
+    .await?;
+
+    for id in &ids {
+        notify_webhook(&state, "entry.deleted", *id, "user");
+    }
+    Ok(Json(serde_json::json!({ "status": "deleted", "deleted": ids.len() })))
+}
+
+#[derive(Serialize)]
+struct TrashEntry {
+    #[serde(flatten)]
+    entry: EntrySummary,
+    deleted_at: DateTime<Utc>,
+    delete_reason: Option<String>,
+    /// End of the restore window; the cleanup may purge the entry any time after this.
+    expires_at: DateTime<Utc>,
+}
+
+/// Soft-deleted entries that haven't been purged yet, most recently deleted first.
+///
+/// Pages with `limit` / `offset` like `list_entries`; the `after` cursor follows the
+/// collection's order, so it doesn't apply here.
+async fn list_trash(
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<PageParams>,
+) -> Result<Response, AppError> {
+    let page = Pagination::from_params(&state, &page)?;
+    if page.after.is_some() {
+        return Err(AppError::bad_request("The trash pages with offset, not after"));
+    }
+    let mut query = QueryBuilder::<Postgres>::new(format!(
+        "SELECT {}, deleted_at, delete_reason FROM entries WHERE deleted_at IS NOT NULL \
+         ORDER BY deleted_at DESC, id DESC",
+        ENTRY_COLUMNS
+    ));
+    page.push(&mut query);
+    let rows = query.build().fetch_all(&state.db).await?;
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entries WHERE deleted_at IS NOT NULL")
+        .fetch_one(&state.db)
+        .await?;
+
+    let entries: Vec<TrashEntry> = rows
+        .into_iter()
+        .map(|row| {
+            let deleted_at: DateTime<Utc> = row.get("deleted_at");
+            let delete_reason: Option<String> = row.get("delete_reason");
+            TrashEntry {
+                entry: entry_summary_from_row(&state, row),
+                deleted_at,
+                delete_reason,
+                expires_at: deleted_at + state.restore_window,
+            }
+        })
+        .collect();
+    Ok(([("x-total-count", total.to_string())], Json(entries)).into_response())
+}
+
+async fn restore_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let row = sqlx::query("SELECT deleted_at FROM entries WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+
+    let Some(row) = row else {
+        return Err(AppError::not_found("Entry not found"));
+    };
+
+    let deleted_at: Option<DateTime<Utc>> = row.get("deleted_at");
+    let deleted_at = deleted_at.ok_or_else(|| AppError::bad_request("Entry not deleted"))?;
+    if Utc::now().signed_duration_since(deleted_at) > state.restore_window {
+        return Err(AppError::bad_request("Restore window expired"));
+    }
+
+    sqlx::query("UPDATE entries SET deleted_at = NULL, delete_reason = NULL, updated_at = NOW() WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+
+    Ok(Json(serde_json::json!({ "status": "restored" })))
+}
+
+async fn toggle_share(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<SharePayload>,
+) -> Result<Json<EntryDetail>, AppError> {
+    if payload.enable {
+        assign_share_token(&state.db, id).await?;
+    } else {
+        let result = sqlx::query(
+            "UPDATE entries SET share_token = NULL, updated_at = NOW() WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .execute(&state.db)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(AppError::not_found("Entry not found"));
+        }
+    }
+
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM entries WHERE id = $1 AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    Ok(Json(entry_detail_from_row(&state, row)))
+}
+
+/// Longest `verified_by` accepted.
+const MAX_VERIFIED_BY_CHARS: usize = 100;
+
+/// Marks an entry's identification as confirmed by a reviewer, or clears that mark.
+async fn verify_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<VerifyPayload>,
+) -> Result<Json<EntryDetail>, AppError> {
+    let verified_by = match payload.verified {
+        true => {
+            let name = payload
+                .verified_by
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| AppError::bad_request("verified_by is required when verifying"))?;
+            if name.chars().count() > MAX_VERIFIED_BY_CHARS {
+                return Err(AppError::bad_request(format!(
+                    "verified_by must be at most {} characters",
+                    MAX_VERIFIED_BY_CHARS
+                )));
+            }
+            Some(name)
+        }
+        false => None,
+    };
+
+    let row = sqlx::query(&format!(
+        "UPDATE entries SET verified = $1, verified_by = $2, updated_at = NOW() \
+         WHERE id = $3 AND deleted_at IS NULL RETURNING {}",
+        ENTRY_COLUMNS
+    ))
+    .bind(payload.verified)
+    .bind(&verified_by)
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    Ok(Json(entry_detail_from_row(&state, row)))
+}
+
+/// How many entries can be pinned to the top of the collection at once.
+const MAX_PINNED_ENTRIES: i64 = 10;
+
+/// Advisory lock key held while pinning; any constant unique to this purpose would do.
+const PIN_LOCK_KEY: i64 = 0x6e64_7069_6e73;
+
+async fn toggle_pin(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<PinPayload>,
+) -> Result<Json<EntryDetail>, AppError> {
+    let result = if payload.enable {
+        // Pins take turns: under READ COMMITTED, two concurrent updates would each count the
+        // pins before the other commits and could both slip in under the cap.
+        let mut tx = state.db.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(PIN_LOCK_KEY)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query(
+            "UPDATE entries SET is_pinned = TRUE, updated_at = NOW() \
+             WHERE id = $1 AND deleted_at IS NULL AND (is_pinned OR \
+             (SELECT COUNT(*) FROM entries WHERE is_pinned AND deleted_at IS NULL) < $2)",
+        )
+        .bind(id)
+        .bind(MAX_PINNED_ENTRIES)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        result
+    } else {
+        sqlx::query(
+            "UPDATE entries SET is_pinned = FALSE, updated_at = NOW() WHERE id = $1 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .execute(&state.db)
+        .await?
+    };
+
+    let row = sqlx::query(&format!(
+        "SELECT {} FROM entries WHERE id = $1 AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    if result.rows_affected() == 0 {
+        return Err(AppError::bad_request(format!(
+            "At most {} entries can be pinned",
+            MAX_PINNED_ENTRIES
+        )));
+    }
+    Ok(Json(entry_detail_from_row(&state, row)))
+}
+
+/// Upper bound on ids accepted by `export_selected`.
+const MAX_EXPORT_IDS: usize = 500;
+
+fn export_entry_from_row(
+    state: &AppState,
+    headers: &HeaderMap,
+    row: &sqlx::postgres::PgRow,
+) -> ExportEntry {
+    let image_path: String = row.get("image_path");
+    ExportEntry {
+        id: row.get("id"),
+        created_at: row.get("created_at"),
+        label: row.get("label"),
+        description: row.get("description"),
+        confidence: row.get("confidence"),
+        tags: row.get("tags"),
+        image_url: absolute_url(headers, &media_url(state, &image_path)),
+        image_file: image_path,
+        image_mime: row.get("image_mime"),
+        image_width: row.get("image_width"),
+        image_height: row.get("image_height"),
+        model: row.get("model"),
+        lang: row.get("lang"),
+        extra: row.get("extra"),
+        reasoning: row.get("reasoning"),
+        alternatives: row.get("alternatives"),
+    }
+}
+
+/// Exports the given entries as JSON, or as a zip of `export.json` plus their images.
+async fn export_selected(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(payload): Json<ExportSelectedPayload>,
+) -> Result<Response, AppError> {
+    if payload.ids.is_empty() {
+        return Err(AppError::bad_request("ids must not be empty"));
+    }
+    if payload.ids.len() > MAX_EXPORT_IDS {
+        return Err(AppError::bad_request(format!(
+            "At most {} ids can be exported at once",
+            MAX_EXPORT_IDS
+        )));
+    }
+
+    let rows = sqlx::query(&format!(
+        "SELECT {}, image_mime, image_width, image_height FROM entries \
+         WHERE id = ANY($1) AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(&payload.ids)
+    .fetch_all(&state.db)
+    .await?;
+
+    let mut by_id: HashMap<Uuid, sqlx::postgres::PgRow> =
+        rows.into_iter().map(|row| (row.get("id"), row)).collect();
+    let mut document = ExportDocument {
+        exported_at: Utc::now(),
+        entries: Vec::new(),
+        missing: Vec::new(),
+    };
+    for id in &payload.ids {
+        match by_id.remove(id) {
+            Some(row) => document.entries.push(export_entry_from_row(&state, &headers, &row)),
+            None if document.entries.iter().any(|entry| entry.id == *id) => {}
+            None => document.missing.push(*id),
+        }
+    }
+
+    if payload.format == ExportFormat::Json {
+        return Ok(Json(document).into_response());
+    }
+
+    let mut archive = zip::ZipWriter::new();
+    archive.add_file("export.json", &serde_json::to_vec_pretty(&document)?);
+    if payload.include_images.unwrap_or(true) {
+        for entry in &document.entries {
+            match tokio::fs::read(state.storage_dir.join(&entry.image_file)).await {
+                Ok(bytes) => archive.add_file(&entry.image_file, &bytes),
+                Err(err) => error!("failed to read {} for export: {}", entry.image_file, err),
+            }
+        }
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"naturadex-export.zip\"",
+            ),
+        ],
+        archive.finish(),
+    )
+        .into_response())
+}
+
+async fn export_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let row = sqlx::query(&format!(
+        "SELECT {}, image_mime, image_width, image_height FROM entries \
+         WHERE id = $1 AND deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(&state.db)
+    .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+
+    let document = EntryExportDocument {
+        exported_at: Utc::now(),
+        entry: export_entry_from_row(&state, &headers, &row),
+    };
+    Ok((
+        [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"naturadex-{}.json\"", id),
+        )],
+        Json(document),
+    )
+        .into_response())
+}
+
+/// Corrects an entry's label, description or tags by hand.
+async fn patch_entry(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<PatchParams>,
+    Json(payload): Json<EntryPatch>,
+) -> Result<Json<EntryDetail>, AppError> {
+    let label = payload.label.map(|label| label.trim().to_string());
+    if label.as_ref().is_some_and(|label| label.is_empty()) {
+        return Err(AppError::bad_request("label must not be empty"));
+    }
+    let description = payload
+        .description
+        .map(|description| description.trim().to_string());
+    let mut tags = payload
+        .tags
+        .map(|tags| normalize_tags(&tags, state.collapse_plural_tags));
+    if params.retag && tags.is_some() {
+        return Err(AppError::bad_request("Send either tags or retag=true, not both"));
+    }
+    if label.is_none() && description.is_none() && tags.is_none() && !params.retag {
+        return Err(AppError::bad_request(
+            "Provide at least one of label, description or tags",
+        ));
+    }
+
+    let row = sqlx::query("SELECT label, description, deleted_at FROM entries WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    if row.get::<Option<DateTime<Utc>>, _>("deleted_at").is_some() {
+        return Err(AppError::bad_request("Deleted entries can't be edited"));
+    }
+    if params.retag {
+        let current_label: String = row.get("label");
+        let current_description: String = row.get("description");
+        let retagged = retag_from_text(
+            &state,
+            label.as_deref().unwrap_or(&current_label),
+            description.as_deref().unwrap_or(&current_description),
+        )
+        .await?;
+        tags = Some(retagged);
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new("UPDATE entries SET edited_at = NOW(), updated_at = NOW()");
+    if let Some(label) = label {
+        query.push(", label = ").push_bind(label);
+    }
+    if let Some(description) = description {
+        query.push(", description = ").push_bind(description);
+    }
+    if let Some(tags) = tags {
+        query.push(", tags = ").push_bind(tags);
+    }
+    query
+        .push(" WHERE id = ")
+        .push_bind(id)
+        .push(format!(" AND deleted_at IS NULL RETURNING {}", ENTRY_COLUMNS));
+    let row = query.build().fetch_optional(&state.db).await?;
+    // Deleted between the check and the update.
+    let row = row.ok_or_else(|| AppError::bad_request("Deleted entries can't be edited"))?;
+    Ok(Json(entry_detail_from_row(&state, row)))
+}
+
+/// Replaces an entry's label with one of its alternatives (or, with `freeform`, any label).
+///
+/// The previous label moves into `alternatives` so the choice can be undone the same way.
+async fn choose_label(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<ChooseLabelPayload>,
+) -> Result<Json<EntryDetail>, AppError> {
+    let label = payload.label.trim().to_string();
+    if label.is_empty() {
+        return Err(AppError::bad_request("label must not be empty"));
+    }
+    if payload.freeform && state.choose_label_strict {
+        return Err(AppError::bad_request(
+            "Freeform labels are disabled; choose one of the entry's alternatives",
+        ));
+    }
+
+    let mut tx = state.db.begin().await?;
+    let row = sqlx::query(
+        "SELECT label, confidence, alternatives FROM entries \
+         WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    let current_label: String = row.get("label");
+    let current_confidence: Option<f64> = row.get("confidence");
+    let alternatives: serde_json::Value = row.get("alternatives");
+    let mut alternatives: Vec<serde_json::Value> = match alternatives {
+        serde_json::Value::Array(items) => items,
+        _ => Vec::new(),
+    };
+
+    let position = alternatives.iter().position(|alt| {
+        alt.get("label")
+            .and_then(|v| v.as_str())
+            .is_some_and(|alt_label| alt_label.eq_ignore_ascii_case(&label))
+    });
+    let confidence = match position {
+        Some(index) => alternatives.remove(index).get("confidence").and_then(|v| v.as_f64()),
+        None if payload.freeform => None,
+        None => {
+            return Err(AppError::bad_request(format!(
+                "'{}' is not one of this entry's alternatives",
+                label
+            )));
+        }
+    };
+    if !current_label.eq_ignore_ascii_case(&label) {
+        alternatives.insert(
+            0,
+            serde_json::json!({ "label": current_label, "confidence": current_confidence }),
+        );
+    }
+
+    sqlx::query(
+        "UPDATE entries SET label = $1, confidence = $2, alternatives = $3, \
+         edited_at = NOW(), updated_at = NOW() WHERE id = $4",
+    )
+    .bind(&label)
+    .bind(confidence)
+    .bind(serde_json::Value::Array(alternatives))
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let row = sqlx::query(&format!("SELECT {} FROM entries WHERE id = $1", ENTRY_COLUMNS))
+        .bind(id)
+        .fetch_one(&state.db)
+        .await?;
+    Ok(Json(entry_detail_from_row(&state, row)))
+}
+
+/// Most entries `export_pdf` will lay out in one guide.
+const MAX_PDF_ENTRIES: usize = 100;
+
+/// Longest edge of images embedded in the PDF field guide.
+const PDF_IMAGE_MAX_DIM: u32 = 1000;
+
+/// A printable field guide: a cover page, then one page per entry (honors `?tag=`),
+/// streamed page by page.
+async fn export_pdf(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ListParams>,
+) -> Result<Response, AppError> {
+    let mut query = QueryBuilder::<Postgres>::new(format!(
+        "SELECT {} FROM entries WHERE deleted_at IS NULL",
+        ENTRY_COLUMNS
+    ));
+    push_list_filters(&mut query, &state, &params);
+    query
+        .push(" ORDER BY created_at ASC LIMIT ")
+        .push_bind(MAX_PDF_ENTRIES as i64 + 1);
+    let rows = query.build().fetch_all(&state.db).await?;
+    if rows.len() > MAX_PDF_ENTRIES {
+        return Err(AppError::bad_request(format!(
+            "The field guide is limited to {} entries; narrow it down with ?tag=",
+            MAX_PDF_ENTRIES
+        )));
+    }
+
+    let subtitle = match params.tag.as_deref().map(str::trim).filter(|tag| !tag.is_empty()) {
+        Some(tag) => format!("{} entries tagged \"{}\"", rows.len(), tag),
+        None => format!("{} entries", rows.len()),
+    };
+
+    // Pages go out as they're built, with one entry's image in memory at a time; the
+    // entry cap above bounds the total work.
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<Bytes, std::io::Error>>(4);
+    tokio::spawn(async move {
+        let margin = 50.0;
+        let text_width = pdf::PAGE_WIDTH - 2.0 * margin;
+        let (mut writer, start) = pdf::PdfWriter::start();
+
+        let mut cover = pdf::Page::new();
+        cover.text(pdf::Font::Bold, 32.0, margin, 560.0, "Naturadex Field Guide");
+        cover.text(pdf::Font::Regular, 14.0, margin, 530.0, &subtitle);
+        cover.text(
+            pdf::Font::Italic,
+            11.0,
+            margin,
+            506.0,
+            &format!("Printed {}", Utc::now().format("%B %-d, %Y")),
+        );
+        let mut chunk = start;
+        chunk.extend(writer.page(cover));
+        if tx.send(Ok(Bytes::from(chunk))).await.is_err() {
+            return;
+        }
+
+        for row in &rows {
+            let id: Uuid = row.get("id");
+            let label: String = row.get("label");
+            let description: String = row.get("description");
+            let tags: Vec<String> = row.get("tags");
+            let created_at: DateTime<Utc> = row.get("created_at");
+            let image_path: String = row.get("image_path");
+
+            let mut page = pdf::Page::new();
+            let mut y = pdf::PAGE_HEIGHT - margin - 24.0;
+            page.text(pdf::Font::Bold, 24.0, margin, y, &label);
+            y -= 18.0;
+            page.text(
+                pdf::Font::Italic,
+                10.0,
+                margin,
+                y,
+                &format!("Found {}", created_at.format("%B %-d, %Y")),
+            );
+            y -= 16.0;
+
+            match pdf_image(&state, &image_path).await {
+                Ok(image) => {
+                    let scale = (text_width / image.width as f32).min(420.0 / image.height as f32);
+                    let (width, height) = (image.width as f32 * scale, image.height as f32 * scale);
+                    y -= height;
+                    page.image(image, margin, y, width, height);
+                    y -= 28.0;
+                }
+                Err(err) => {
+                    error!("failed to embed image for {} in PDF: {}", id, err.message);
+                    y -= 10.0;
+                }
+            }
+
+            for line in pdf::wrap(&description, pdf::Font::Regular, 12.0, text_width) {
+                if y < margin {
+                    break;
+                }
+                page.text(pdf::Font::Regular, 12.0, margin, y, &line);
+                y -= 16.0;
+            }
+            if !tags.is_empty() && y >= margin + 10.0 {
+                y -= 8.0;
+                let tag_line = format!("Tags: {}", tags.join(", "));
+                for line in pdf::wrap(&tag_line, pdf::Font::Italic, 10.0, text_width) {
+                    if y < margin {
+                        break;
+                    }
+                    page.text(pdf::Font::Italic, 10.0, margin, y, &line);
+                    y -= 13.0;
+                }
+            }
+            if tx.send(Ok(Bytes::from(writer.page(page)))).await.is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(Ok(Bytes::from(writer.finish()))).await;
+    });
+
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/pdf"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"naturadex-field-guide.pdf\"",
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+/// Loads an entry image as a downscaled RGB JPEG, the one image type `pdf` embeds.
+async fn pdf_image(state: &AppState, image_path: &str) -> Result<pdf::JpegImage, AppError> {
+    let bytes = tokio::fs::read(state.storage_dir.join(image_path)).await?;
+    let img = decode_image(state, &bytes)?;
+    let rgb = DynamicImage::ImageRgb8(
+        img.thumbnail(PDF_IMAGE_MAX_DIM, PDF_IMAGE_MAX_DIM).to_rgb8(),
+    );
+    Ok(pdf::JpegImage {
+        width: rgb.width(),
+        height: rgb.height(),
+        data: encode_image(&rgb, ImageFormat::Jpeg)?,
+    })
+}
+
+/// Records whether an entry's classification was right, snapshotting its label and model.
+async fn submit_feedback(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<FeedbackPayload>,
+) -> Result<Json<FeedbackResponse>, AppError> {
+    let corrected_label = payload
+        .corrected_label
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty());
+    if payload.correct && corrected_label.is_some() {
+        return Err(AppError::bad_request(
+            "corrected_label is only allowed when correct is false",
+        ));
+    }
+
+    let row = sqlx::query("SELECT label, model FROM entries WHERE id = $1 AND deleted_at IS NULL")
+        .bind(id)
+        .fetch_optional(&state.db)
+        .await?;
+    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
+    let label: String = row.get("label");
+    let model: Option<String> = row.get("model");
+
+    let feedback_id = Uuid::new_v4();
+    let created_at: DateTime<Utc> = sqlx::query_scalar(
+        "INSERT INTO classification_feedback (id, entry_id, model, label, correct, corrected_label) \
+         VALUES ($1, $2, $3, $4, $5, $6) RETURNING created_at",
+    )
+    .bind(feedback_id)
+    .bind(id)
+    .bind(&model)
+    .bind(&label)
+    .bind(payload.correct)
+    .bind(&corrected_label)
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(FeedbackResponse {
+        id: feedback_id,
+        entry_id: id,
+        model,
+        label,
+        correct: payload.correct,
+        corrected_label,
+        created_at,
+    }))
+}
+
+async fn accuracy_stats(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ModelAccuracy>>, AppError> {
+    let rows = sqlx::query(
+        "SELECT COALESCE(model, 'unknown') AS model, \
+         COUNT(*) FILTER (WHERE correct) AS correct, \
+         COUNT(*) FILTER (WHERE NOT correct) AS incorrect \
+         FROM classification_feedback GROUP BY 1 ORDER BY 1",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let stats = rows
+        .into_iter()
+        .map(|row| {
+            let correct: i64 = row.get("correct");
+            let incorrect: i64 = row.get("incorrect");
+            ModelAccuracy {
+                model: row.get("model"),
+                correct,
+                incorrect,
+                accuracy: correct as f64 / (correct + incorrect) as f64,
+            }
+        })
+        .collect();
+
+    Ok(Json(stats))
+}
+
+async fn model_stats(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ModelUsage>>, AppError> {
+    let rows = sqlx::query(
+        "SELECT model, COUNT(*) AS entries, \
+         MIN(created_at) AS first_created_at, MAX(created_at) AS last_created_at \
+         FROM entries WHERE deleted_at IS NULL GROUP BY model ORDER BY entries DESC",
+    )
+    .fetch_all(&state.db)
+    .await?;
+
+    let stats = rows
+        .into_iter()
+        .map(|row| ModelUsage {
+            model: row.get("model"),
+            entries: row.get("entries"),
+            first_created_at: row.get("first_created_at"),
+            last_created_at: row.get("last_created_at"),
+        })
+        .collect();
+
+    Ok(Json(stats))
+}
+
+#[derive(Deserialize)]
+struct UsageParams {
+    /// First and last UTC day (`YYYY-MM-DD`, inclusive) of entry creation to count.
+    from: Option<String>,
+    to: Option<String>,
+}
+
+#[derive(Serialize)]
+struct UsageTotals {
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+    /// Entries in the range with recorded usage.
+    entries: i64,
+    input_tokens: i64,
+    output_tokens: i64,
+}
+
+/// Anthropic tokens spent on classification, summed over entries created in the range.
+/// Deleted entries still count until purged, since their tokens were spent all the same.
+async fn token_usage(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<UsageParams>,
+) -> Result<Json<UsageTotals>, AppError> {
+    let (from, to) = parse_day_range(params.from.as_deref(), params.to.as_deref())?;
+
+    let row = sqlx::query(&format!(
+        "SELECT COUNT(*) AS entries, \
+         COALESCE(SUM(input_tokens), 0)::bigint AS input_tokens, \
+         COALESCE(SUM(output_tokens), 0)::bigint AS output_tokens \
+         FROM entries WHERE (input_tokens IS NOT NULL OR output_tokens IS NOT NULL) AND {}",
+        CREATED_DAY_RANGE_SQL
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_one(&state.db)
+    .await?;
+
+    Ok(Json(UsageTotals {
+        from,
+        to,
+        entries: row.get("entries"),
+        input_tokens: row.get("input_tokens"),
+        output_tokens: row.get("output_tokens"),
+    }))
+}
+
+/// `from` / `to` query values as UTC days (`YYYY-MM-DD`, inclusive); missing means open-ended.
+/// Match them against `created_at` with `CREATED_DAY_RANGE_SQL`.
+fn parse_day_range(
+    from: Option<&str>,
+    to: Option<&str>,
+) -> Result<(Option<chrono::NaiveDate>, Option<chrono::NaiveDate>), AppError> {
+    let parse_day = |field: &str, value: Option<&str>| {
+        value
+            .map(|value| {
+                chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").map_err(|_| {
+                    AppError::bad_request(format!("{} must be a date like 2024-05-31", field))
+                })
+            })
+            .transpose()
+    };
+    let from = parse_day("from", from)?;
+    let to = parse_day("to", to)?;
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return Err(AppError::bad_request("from must not be after to"));
+    }
+    Ok((from, to))
+}
+
+/// Limits `created_at` to the days bound as `$1` (from) and `$2` (to), either may be null.
+const CREATED_DAY_RANGE_SQL: &str = "($1::date IS NULL OR (created_at AT TIME ZONE 'UTC')::date >= $1) \
+     AND ($2::date IS NULL OR (created_at AT TIME ZONE 'UTC')::date <= $2)";
+
+/// How many fresh tokens `assign_share_token` tries before giving up.
+const SHARE_TOKEN_ATTEMPTS: usize = 5;
+
+/// Shares the entry, keeping its token if it already has one so links handed out earlier
+/// keep working; a new token is regenerated if it collides with an existing one.
+/// Deleted entries can't be shared: `404` for them as for unknown ids.
+async fn assign_share_token(db: &PgPool, id: Uuid) -> Result<String, AppError> {
+    assign_share_token_with(db, id, || Uuid::new_v4().to_string()).await
+}
+
+async fn assign_share_token_with(
+    db: &PgPool,
+    id: Uuid,
+    mut new_token: impl FnMut() -> String,
+) -> Result<String, AppError> {
+    for _ in 0..SHARE_TOKEN_ATTEMPTS {
+        let token = new_token();
+        let result = sqlx::query_scalar::<_, String>(
+            "UPDATE entries SET share_token = COALESCE(share_token, $1), \
+             updated_at = CASE WHEN share_token IS NULL THEN NOW() ELSE updated_at END \
+             WHERE id = $2 AND deleted_at IS NULL RETURNING share_token",
+        )
+        .bind(&token)
+        .bind(id)
+        .fetch_optional(db)
+        .await;
+
+        match result {
+            Ok(Some(token)) => return Ok(token),
+            Ok(None) => return Err(AppError::not_found("Entry not found")),
+            Err(err) if is_share_token_conflict(&err) => {
+                warn!("share token collision for entry {}, regenerating", id);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Err(AppError::internal("Could not generate a unique share token"))
+}
+
+fn is_share_token_conflict(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Database(db_err) => {
+            db_err.is_unique_violation() && db_err.constraint() == Some("entries_share_token_key")
+        }
+        _ => false,
+    }
+}
+
+/// Anthropic rejects images whose base64 payload exceeds 5 MB.
+const ANTHROPIC_MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+/// Language tags like `es` or `pt-br`.
+fn is_valid_lang(lang: &str) -> bool {
+    lang.len() <= 16
+        && lang.split('-').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Parses `CLASSIFICATION_EXTRA_FIELDS` (`name=description;name=description`).
+fn parse_extra_fields(value: &str) -> anyhow::Result<Vec<ExtraField>> {
+    const RESERVED: &[&str] = &["label", "description", "tags", "confidence", "extra"];
+
+    let mut fields: Vec<ExtraField> = Vec::new();
+    for spec in value.split(';').map(str::trim).filter(|spec| !spec.is_empty()) {
+        let (name, description) = spec.split_once('=').unwrap_or((spec, ""));
+        let name = name.trim();
+        let is_identifier = name.len() <= 40
+            && name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_identifier {
+            anyhow::bail!(
+                "Invalid CLASSIFICATION_EXTRA_FIELDS name '{}': use lowercase letters, digits and underscores",
+                name
+            );
+        }
+        if RESERVED.contains(&name) || fields.iter().any(|field| field.name == name) {
+            anyhow::bail!("Duplicate or reserved CLASSIFICATION_EXTRA_FIELDS name '{}'", name);
+        }
+        fields.push(ExtraField {
+            name: name.to_string(),
+            description: description.trim().to_string(),
+        });
+    }
+    Ok(fields)
+}
+
+/// Built-in classification prompt; `CLASSIFICATION_PROMPT_TEMPLATE` replaces it.
+const DEFAULT_CLASSIFICATION_PROMPT: &str = "Identify the natural scene. Return strict JSON with fields: label (short name), description (1-2 sentences), tags (array of {tag_min}-{tag_max} lowercase words), confidence (0-1), alternatives (array of up to 3 other plausible identifications as {{label, confidence}}, empty if you are sure). No markdown.";
+
+/// Variables a prompt template can reference as `{name}`.
+#[derive(Clone, Copy, PartialEq)]
+enum PromptVariable {
+    /// The requested `lang`, `en` when none was given.
+    Lang,
+    /// The upload's `hint`, empty when none was given.
+    Hint,
+    /// Category names from `CATEGORY_RULES`, comma-separated.
+    CategoryList,
+    TagMin,
+    TagMax,
+}
+
+impl TemplateVariable for PromptVariable {
+    const ALL: &'static [(&'static str, Self)] = &[
+        ("lang", PromptVariable::Lang),
+        ("hint", PromptVariable::Hint),
+        ("category_list", PromptVariable::CategoryList),
+        ("tag_min", PromptVariable::TagMin),
+        ("tag_max", PromptVariable::TagMax),
+    ];
+}
+
+type PromptTemplate = Template<PromptVariable>;
+
+/// Entry fields an `ENTRY_TITLE_TEMPLATE` can reference as `{name}`.
+#[derive(Clone, Copy, PartialEq)]
+enum TitleField {
+    Label,
+    /// UTC creation day, `YYYY-MM-DD`.
+    Date,
+    /// Rounded percentage like `92%`; empty when unknown.
+    Confidence,
+    /// Camera make and model as in `/api/entries/by-device`; empty when unknown.
+    Device,
+}
+
+impl TemplateVariable for TitleField {
+    const ALL: &'static [(&'static str, Self)] = &[
+        ("label", TitleField::Label),
+        ("date", TitleField::Date),
+        ("confidence", TitleField::Confidence),
+        ("device", TitleField::Device),
+    ];
+}
+
+/// The placeholders a kind of `Template` accepts, by name.
+trait TemplateVariable: Copy + PartialEq + 'static {
+    const ALL: &'static [(&'static str, Self)];
+}
+
+#[derive(Clone)]
+enum TemplatePart<V> {
+    Text(String),
+    Variable(V),
+}
+
+/// Text with `{variable}` placeholders; `{{` and `}}` are literal braces.
+#[derive(Clone)]
+struct Template<V> {
+    parts: Vec<TemplatePart<V>>,
+}
+
+impl<V: TemplateVariable> Template<V> {
+    /// Parses `template`, refusing unknown variables and unbalanced braces.
+    fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => return Err(format!("unclosed '{{{}'", name)),
+                        }
+                    }
+                    let variable = V::ALL
+                        .iter()
+                        .find(|(known, _)| *known == name.trim())
+                        .map(|(_, variable)| *variable)
+                        .ok_or_else(|| {
+                            let names: Vec<&str> =
+                                V::ALL.iter().map(|(name, _)| *name).collect();
+                            format!(
+                                "unknown variable '{{{}}}': expected one of {}",
+                                name,
+                                names.join(", ")
+                            )
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Variable(variable));
+                }
+                '}' => return Err("unmatched '}' (write '}}' for a literal brace)".to_string()),
+                _ => text.push(ch),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(Self { parts })
+    }
+
+    fn uses(&self, variable: V) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, TemplatePart::Variable(used) if *used == variable))
+    }
+
+    fn render(&self, value: impl Fn(V) -> String) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Text(text) => text.clone(),
+                TemplatePart::Variable(variable) => value(*variable),
+            })
+            .collect()
+    }
+}
+
+/// Renders the prompt template, then appends what the response parsing depends on:
+/// extra fields, the answer language and `reasoning`.
+fn classification_prompt(state: &AppState, options: &ClassifyOptions) -> String {
+    let template = &state.prompt_template;
+    let mut prompt = template.render(|variable| match variable {
+        PromptVariable::Lang => options.lang.clone().unwrap_or_else(|| "en".to_string()),
+        PromptVariable::Hint => options.hint.clone().unwrap_or_default(),
+        PromptVariable::CategoryList => state.prompt_categories.clone(),
+        PromptVariable::TagMin => state.prompt_tag_range.0.to_string(),
+        PromptVariable::TagMax => state.prompt_tag_range.1.to_string(),
+    });
+    if let Some(hint) = &options.hint
+        && !template.uses(PromptVariable::Hint)
+    {
+        prompt.push_str(&format!(" The photographer adds this hint: \"{}\".", hint));
+    }
+    if !state.extra_fields.is_empty() {
+        let fields: Vec<String> = state
+            .extra_fields
+            .iter()
+            .map(|field| match field.description.as_str() {
+                "" => field.name.clone(),
+                description => format!("{} ({})", field.name, description),
+            })
+            .collect();
+        prompt.push_str(&format!(
+            " Also include an \"extra\" object with these fields: {}.",
+            fields.join(", ")
+        ));
+    }
+    if let Some(lang) = options.lang.as_deref().filter(|lang| *lang != "en") {
+        prompt.push_str(&format!(
+            " Write label and description in the language with code \"{}\". Always write tags in English so they stay filterable across languages.",
+            lang
+        ));
+    }
+    if !options.generate_tags {
+        prompt.push_str(r#" Skip the tags: return "tags" as an empty array."#);
+    }
+    if options.explain {
+        prompt.push_str(
+            " Also include \"reasoning\": 2-4 sentences on the visual features that led to this identification, written for a curious learner.",
+        );
+    }
+    prompt
+}
+
+/// The model provider: turns images into a `Classification`, and answers the other
+/// questions the pipeline asks (retagging, moderation), so nothing else calls a provider
+/// directly.
+trait Classifier: Send + Sync {
+    fn classify<'a>(
+        &'a self,
+        state: &'a AppState,
+        bytes: &'a [u8],
+        mime: &'a str,
+        options: &'a ClassifyOptions,
+    ) -> BoxFuture<'a, Result<Classification, AppError>>;
+
+    /// Several images at once for `mode=combined`, one result per image; `None` marks an
+    /// image to classify on its own. By default every image is.
+    fn classify_combined<'a>(
+        &'a self,
+        _state: &'a AppState,
+        images: &'a [(&'a [u8], &'a str)],
+        _options: &'a ClassifyOptions,
+    ) -> BoxFuture<'a, Result<Vec<Option<Classification>>, AppError>> {
+        Box::pin(async move { Ok(images.iter().map(|_| None).collect()) })
+    }
+
+    /// Tags for an entry from its label and description alone, before normalization.
+    fn retag<'a>(
+        &'a self,
+        state: &'a AppState,
+        label: &'a str,
+        description: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>>;
+
+    /// The model's verdict on an upload under the operator's `MODERATION_PROMPT`.
+    fn moderate<'a>(
+        &'a self,
+        state: &'a AppState,
+        moderation: &'a ModerationConfig,
+        bytes: &'a [u8],
+        mime: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, AppError>>;
+
+    fn needs_api_key(&self) -> bool {
+        true
+    }
+
+    /// The model used when an upload doesn't ask for one; `ANTHROPIC_MODEL` unless overridden.
+    fn default_model(&self) -> Option<&str> {
+        None
+    }
+}
+
+fn classification_model(state: &AppState) -> &str {
+    state
+        .classifier
+        .default_model()
+        .unwrap_or(&state.anthropic_model)
+}
+
+/// Asks the configured Anthropic model; the default.
+struct AnthropicClassifier;
+
+impl Classifier for AnthropicClassifier {
+    fn classify<'a>(
+        &'a self,
+        state: &'a AppState,
+        bytes: &'a [u8],
+        mime: &'a str,
+        options: &'a ClassifyOptions,
+    ) -> BoxFuture<'a, Result<Classification, AppError>> {
+        Box::pin(classify_image(state, bytes, mime, options))
+    }
+
+    fn classify_combined<'a>(
+        &'a self,
+        state: &'a AppState,
+        images: &'a [(&'a [u8], &'a str)],
+        options: &'a ClassifyOptions,
+    ) -> BoxFuture<'a, Result<Vec<Option<Classification>>, AppError>> {
+        Box::pin(classify_images_combined(state, images, options))
+    }
+
+    fn retag<'a>(
+        &'a self,
+        state: &'a AppState,
+        label: &'a str,
+        description: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "model": state.anthropic_model,
+                "max_tokens": RETAG_MAX_TOKENS,
+                "system": CLASSIFY_SYSTEM_PROMPT,
+                "messages": [{ "role": "user", "content": retag_question(state, label, description) }]
+            });
+            let response = send_anthropic(state, &body).await?;
+            parse_retag_answer(response_text(&response))
+        })
+    }
+
+    fn moderate<'a>(
+        &'a self,
+        state: &'a AppState,
+        moderation: &'a ModerationConfig,
+        bytes: &'a [u8],
+        mime: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, AppError>> {
+        Box::pin(async move {
+            let b64 = encode_for_anthropic(bytes)?;
+            let body = serde_json::json!({
+                "model": state.anthropic_model,
+                "max_tokens": moderation.max_tokens,
+                "system": moderation.prompt,
+                "messages": [
+                    {
+                        "role": "user",
+                        "content": [
+                            {
+                                "type": "image",
+                                "source": {
+                                    "type": "base64",
+                                    "media_type": mime,
+                                    "data": b64
+                                }
+                            },
+                            { "type": "text", "text": MODERATION_QUESTION }
+                        ]
+                    }
+                ]
+            });
+            let response = send_anthropic(state, &body).await?;
+            parse_moderation_answer(response_text(&response))
+        })
+    }
+}
+
+/// `CLASSIFIER=mock`: a fixed answer derived from the image bytes, without any network
+/// call, for local development and exercising the upload path without an API key.
+/// The same image always gets the same label; retagging and moderation are canned too.
+struct MockClassifier;
+
+impl Classifier for MockClassifier {
+    fn classify<'a>(
+        &'a self,
+        _state: &'a AppState,
+        bytes: &'a [u8],
+        mime: &'a str,
+        options: &'a ClassifyOptions,
+    ) -> BoxFuture<'a, Result<Classification, AppError>> {
+        Box::pin(async move {
+            let hash = content_hash(bytes);
+            Ok(Classification {
+                label: format!("Mock subject {}", &hash[..8]),
+                description: format!(
+                    "A {} image of {} bytes, classified by the mock classifier.",
+                    mime,
+                    bytes.len()
+                ),
+                tags: vec!["mock".to_string()],
+                confidence: Some(0.5),
+                extra: serde_json::Map::new(),
+                reasoning: options
+                    .explain
+                    .then(|| "The mock classifier doesn't look at the image.".to_string()),
+                alternatives: Vec::new(),
+                usage: None,
+            })
+        })
+    }
+
+    fn retag<'a>(
+        &'a self,
+        _state: &'a AppState,
+        _label: &'a str,
+        _description: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>> {
+        Box::pin(async move { Ok(vec!["mock".to_string(), "retagged".to_string()]) })
+    }
+
+    /// Allows everything.
+    fn moderate<'a>(
+        &'a self,
+        _state: &'a AppState,
+        _moderation: &'a ModerationConfig,
+        _bytes: &'a [u8],
+        _mime: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, AppError>> {
+        Box::pin(async move {
+            Ok(ModerationVerdict {
+                allowed: true,
+                score: Some(0.0),
+                reason: None,
+            })
+        })
+    }
+
+    fn needs_api_key(&self) -> bool {
+        false
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        Some("mock")
+    }
+}
+
+/// `CLASSIFIER=openai`: the same prompts and answer parsing against OpenAI's chat
+/// completions API, with images as data URIs. Few-shot examples aren't sent.
+struct OpenAiClassifier {
+    api_key: String,
+    model: String,
+}
+
+impl Classifier for OpenAiClassifier {
+    fn classify<'a>(
+        &'a self,
+        state: &'a AppState,
+        bytes: &'a [u8],
+        mime: &'a str,
+        options: &'a ClassifyOptions,
+    ) -> BoxFuture<'a, Result<Classification, AppError>> {
+        Box::pin(self.classify_image(state, bytes, mime, options))
+    }
+
+    fn retag<'a>(
+        &'a self,
+        state: &'a AppState,
+        label: &'a str,
+        description: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<String>, AppError>> {
+        Box::pin(async move {
+            let messages = serde_json::json!([
+                { "role": "system", "content": CLASSIFY_SYSTEM_PROMPT },
+                { "role": "user", "content": retag_question(state, label, description) }
+            ]);
+            let response = self.chat(state, messages, RETAG_MAX_TOKENS).await?;
+            parse_retag_answer(openai_text(&response))
+        })
+    }
+
+    fn moderate<'a>(
+        &'a self,
+        state: &'a AppState,
+        moderation: &'a ModerationConfig,
+        bytes: &'a [u8],
+        mime: &'a str,
+    ) -> BoxFuture<'a, Result<ModerationVerdict, AppError>> {
+        Box::pin(async move {
+            let messages = serde_json::json!([
+                { "role": "system", "content": moderation.prompt },
+                {
+                    "role": "user",
+                    "content": [
+                        { "type": "image_url", "image_url": { "url": openai_data_uri(bytes, mime) } },
+                        { "type": "text", "text": MODERATION_QUESTION }
+                    ]
+                }
+            ]);
+            let response = self.chat(state, messages, moderation.max_tokens).await?;
+            parse_moderation_answer(openai_text(&response))
+        })
+    }
+
+    fn needs_api_key(&self) -> bool {
+        false
+    }
+
+    fn default_model(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+}
+
+impl OpenAiClassifier {
+    async fn classify_image(
+        &self,
+        state: &AppState,
+        bytes: &[u8],
+        mime: &str,
+        options: &ClassifyOptions,
+    ) -> Result<Classification, AppError> {
+        let question = serde_json::json!({
+            "role": "user",
+            "content": [
+                { "type": "image_url", "image_url": { "url": openai_data_uri(bytes, mime) } },
+                { "type": "text", "text": classification_prompt(state, options) }
+            ]
+        });
+        let body = serde_json::json!({
+            "model": options.model,
+            "max_tokens": if options.explain { 1024 } else { 512 },
+            "response_format": { "type": "json_object" },
+            "messages": [
+                { "role": "system", "content": CLASSIFY_SYSTEM_PROMPT },
+                question
+            ]
+        });
+
+        let response =
+            send_with_retries(state, "OpenAI", || send_openai_once(state, &self.api_key, &body)).await?;
+        let text = openai_text(&response);
+        let json_text = extract_json(text).unwrap_or_else(|| text.to_string());
+        let value: serde_json::Value = serde_json::from_str(&json_text).map_err(|e| {
+            AppError::upstream(format!("Failed to parse classification JSON: {}", e))
+        })?;
+        let mut parsed = parse_classification(state, options, value)?;
+        parsed.usage = response.get("usage").and_then(|usage| {
+            Some(TokenUsage {
+                input_tokens: usage.get("prompt_tokens")?.as_i64()? as i32,
+                output_tokens: usage.get("completion_tokens")?.as_i64()? as i32,
+            })
+        });
+        if parsed.confidence.is_none() {
+            parsed.confidence = match state.missing_confidence {
+                MissingConfidence::Null => None,
+                MissingConfidence::Default(value) => Some(value),
+                MissingConfidence::Retry => {
+                    let messages = serde_json::json!([
+                        { "role": "system", "content": CLASSIFY_SYSTEM_PROMPT },
+                        question,
+                        { "role": "assistant", "content": text },
+                        { "role": "user", "content": CONFIDENCE_FOLLOW_UP }
+                    ]);
+                    match self.chat(state, messages, CONFIDENCE_MAX_TOKENS).await {
+                        Ok(response) => parse_confidence_answer(openai_text(&response)),
+                        Err(err) => {
+                            warn!("confidence follow-up failed: {}", err.message);
+                            None
+                        }
+                    }
+                }
+            };
+        }
+        Ok(parsed)
+    }
+
+    /// A plain chat completion with the configured model, for the questions besides
+    /// classification.
+    async fn chat(
+        &self,
+        state: &AppState,
+        messages: serde_json::Value,
+        max_tokens: usize,
+    ) -> Result<serde_json::Value, AppError> {
+        let body = serde_json::json!({
+            "model": self.model,
+            "max_tokens": max_tokens,
+            "response_format": { "type": "json_object" },
+            "messages": messages
+        });
+        send_with_retries(state, "OpenAI", || send_openai_once(state, &self.api_key, &body)).await
+    }
+}
+
+fn openai_data_uri(bytes: &[u8], mime: &str) -> String {
+    format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+/// The first choice's message text of a chat completion; empty when there is none.
+fn openai_text(response: &serde_json::Value) -> &str {
+    response
+        .pointer("/choices/0/message/content")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+}
+
+async fn send_openai_once(
+    state: &AppState,
+    api_key: &str,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, UpstreamFailure> {
+    let res = state
+        .http
+        .post("https://api.openai.com/v1/chat/completions")
+        .bearer_auth(api_key)
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| UpstreamFailure::Retryable {
+            error: AppError::upstream(format!("Failed to reach OpenAI: {}", e)),
+            retry_after: None,
+        })?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok());
+        let text = res.text().await.unwrap_or_default();
+        // OpenAI wraps failures as `{"error": {"message", "type", "code"}}`.
+        let message = serde_json::from_str::<serde_json::Value>(&text)
+            .ok()
+            .and_then(|value| {
+                let error = value.get("error")?;
+                let message = error.get("message")?.as_str()?.to_string();
+                Some(match error.get("code").and_then(|code| code.as_str()) {
+                    Some(code) => format!("{} ({})", message, code),
+                    None => message,
+                })
+            })
+            .unwrap_or(text);
+        let error = match status.as_u16() {
+            429 => AppError::throttled(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("OpenAI rate limit reached: {}", message),
+                retry_after.unwrap_or(ANTHROPIC_DEFAULT_RETRY_AFTER_SECS),
+            ),
+            _ => AppError::upstream(format!("OpenAI error {}: {}", status, message)),
+        };
+        return Err(match status.as_u16() {
+            429 | 500 | 502 | 503 => UpstreamFailure::Retryable { error, retry_after },
+            _ => UpstreamFailure::Fatal(error),
+        });
+    }
+
+    res.json().await.map_err(|e| {
+        UpstreamFailure::Fatal(AppError::upstream(format!(
+            "Failed to parse OpenAI response: {}",
+            e
+        )))
+    })
+}
+
+async fn classify_image(
+    state: &AppState,
+    bytes: &[u8],
+    mime: &str,
+    options: &ClassifyOptions,
+) -> Result<Classification, AppError> {
+    let b64 = encode_for_anthropic(bytes)?;
+
+    let prompt = classification_prompt(state, options);
+
+    let mut content = few_shot_content(state);
+    content.push(serde_json::json!({
+        "type": "image",
+        "source": {
+            "type": "base64",
+            "media_type": mime,
+            "data": b64
+        }
+    }));
+    content.push(serde_json::json!({
+        "type": "text",
+        "text": prompt
+    }));
+    let question = serde_json::json!({
+        "role": "user",
+        "content": content
+    });
+    let body = serde_json::json!({
+        "model": options.model,
+        "max_tokens": if options.explain { 1024 } else { 512 },
+        "system": CLASSIFY_SYSTEM_PROMPT,
+        "messages": [question]
+    });
+
+    let response = send_anthropic(state, &body).await?;
+    let text = response_text(&response);
+
+    let json_text = extract_json(text).unwrap_or_else(|| text.to_string());
+    let value: serde_json::Value = serde_json::from_str(&json_text).map_err(|e| {
+        AppError::upstream(format!("Failed to parse classification JSON: {}", e))
+    })?;
+    let mut parsed = parse_classification(state, options, value)?;
+    parsed.usage = TokenUsage::from_response(&response);
+
+    if parsed.confidence.is_none() {
+        parsed.confidence = match state.missing_confidence {
+            MissingConfidence::Null => None,
+            MissingConfidence::Default(value) => Some(value),
+            MissingConfidence::Retry => {
+                ask_for_confidence(state, options, question, text).await
+            }
+        };
+    }
+
+    Ok(parsed)
+}
+
+/// Message blocks showing the `FEW_SHOT_EXAMPLES`, ending with a lead-in to the actual
+/// photo; empty when none are configured.
+fn few_shot_content(state: &AppState) -> Vec<serde_json::Value> {
+    if state.few_shot_examples.is_empty() {
+        return Vec::new();
+    }
+    let mut content = vec![serde_json::json!({
+        "type": "text",
+        "text": "Reference examples from this collection, each followed by its correct label:"
+    })];
+    for (index, example) in state.few_shot_examples.iter().enumerate() {
+        content.push(serde_json::json!({
+            "type": "image",
+            "source": { "type": "base64", "media_type": "image/jpeg", "data": example.data }
+        }));
+        content.push(serde_json::json!({
+            "type": "text",
+            "text": format!("Example {}: {}", index + 1, example.label)
+        }));
+    }
+    content.push(serde_json::json!({
+        "type": "text",
+        "text": "Now the photo to classify. Use the examples as a guide to this collection's subjects and naming, not as the only possible answers:"
+    }));
+    content
+}
+
+/// Turns one classification object from the model into a `Classification`.
+fn parse_classification(
+    state: &AppState,
+    options: &ClassifyOptions,
+    value: serde_json::Value,
+) -> Result<Classification, AppError> {
+    let mut parsed: Classification = serde_json::from_value(value.clone()).map_err(|e| {
+        AppError::upstream(format!("Failed to parse classification JSON: {}", e))
+    })?;
+    parsed.extra = collect_extra_fields(state, &value);
+    if !options.explain {
+        parsed.reasoning = None;
+    }
+    parsed.alternatives = collect_alternatives(&parsed.label, &value);
+    Ok(parsed)
+}
+
+/// Classifies several images with one Anthropic message, returning one result per image.
+///
+/// An image whose answer is missing, duplicated or unparseable gets `None` so the caller can
+/// classify it on its own. A missing `confidence` is never followed up here, even with
+/// `MISSING_CONFIDENCE=retry`, since that would mean another call per image.
+async fn classify_images_combined(
+    state: &AppState,
+    images: &[(&[u8], &str)],
+    options: &ClassifyOptions,
+) -> Result<Vec<Option<Classification>>, AppError> {
+    let mut content = Vec::with_capacity(images.len() * 2 + 1);
+    for (index, (bytes, mime)) in images.iter().enumerate() {
+        content.push(serde_json::json!({ "type": "text", "text": format!("Image {}:", index + 1) }));
+        content.push(serde_json::json!({
+            "type": "image",
+            "source": {
+                "type": "base64",
+                "media_type": mime,
+                "data": encode_for_anthropic(bytes)?
+            }
+        }));
+    }
+    content.push(serde_json::json!({
+        "type": "text",
+        "text": format!(
+            "{} There are {} images above, each preceded by its number. Apply these instructions to each image separately and return strict JSON {{\"results\": [...]}} with one object per image, in order, each with an added \"image\" field holding its number.",
+            classification_prompt(state, options),
+            images.len()
+        )
+    }));
+    let per_image_tokens = if options.explain { 1024 } else { 512 };
+    let body = serde_json::json!({
+        "model": options.model,
+        "max_tokens": per_image_tokens * images.len(),
+        "system": CLASSIFY_SYSTEM_PROMPT,
+        "messages": [{ "role": "user", "content": content }]
+    });
+
+    let response = send_anthropic(state, &body).await?;
+    let text = response_text(&response);
+    let json_text = extract_json(text).unwrap_or_else(|| text.to_string());
+    let value: serde_json::Value = serde_json::from_str(&json_text).map_err(|e| {
+        AppError::upstream(format!("Failed to parse batch classification JSON: {}", e))
+    })?;
+    // Per-image usage isn't reported, so each image is charged an equal share.
+    let usage = TokenUsage::from_response(&response).map(|usage| usage.split(images.len()));
+    let results = value
+        .get("results")
+        .and_then(|results| results.as_array())
+        .ok_or_else(|| AppError::upstream("Batch classification has no results array"))?;
+
+    // Trust the numbering when the model gives it; fall back to position only when every
+    // answer is present and none are numbered.
+    let numbered = results.iter().any(|result| result.get("image").is_some());
+    if !numbered && results.len() != images.len() {
+        warn!(
+            "batch classification returned {} unnumbered results for {} images",
+            results.len(),
+            images.len()
+        );
+        return Ok(images.iter().map(|_| None).collect());
+    }
+    let mut classifications: Vec<Option<Classification>> = images.iter().map(|_| None).collect();
+    let mut seen = vec![false; images.len()];
+    for (position, result) in results.iter().enumerate() {
+        let index = match numbered {
+            true => match result.get("image").and_then(|v| v.as_u64()) {
+                Some(number) if number >= 1 && (number as usize) <= images.len() => {
+                    number as usize - 1
+                }
+                _ => continue,
+            },
+            false => position,
+        };
+        if std::mem::replace(&mut seen[index], true) {
+            // Two answers for one image: trust neither.
+            classifications[index] = None;
+            continue;
+        }
+        match parse_classification(state, options, result.clone()) {
+            Ok(mut parsed) => {
+                parsed.usage = usage;
+                if parsed.confidence.is_none()
+                    && let MissingConfidence::Default(value) = state.missing_confidence
+                {
+                    parsed.confidence = Some(value);
+                }
+                classifications[index] = Some(parsed);
+            }
+            Err(err) => warn!("batch result for image {}: {}", index + 1, err.message),
+        }
+    }
+    Ok(classifications)
+}
+
+const CLASSIFY_SYSTEM_PROMPT: &str = "You are a friendly nature guide who classifies landscapes, plants, animals, and weather. Avoid brand names. Be concise.";
+
+/// Asks the model for tags matching an entry's label and description, without the image.
+///
+/// Cheap next to a reclassify; the result goes through the same normalization and
+/// `DEFAULT_TAGS` as classified tags.
+async fn retag_from_text(state: &AppState, label: &str, description: &str) -> Result<Vec<String>, AppError> {
+    let tags = state.classifier.retag(state, label, description).await?;
+    let mut tags = normalize_tags(&tags, state.collapse_plural_tags);
+    for tag in &state.default_tags {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    Ok(tags)
+}
+
+const RETAG_MAX_TOKENS: usize = 256;
+
+fn retag_question(state: &AppState, label: &str, description: &str) -> String {
+    let (tag_min, tag_max) = state.prompt_tag_range;
+    format!(
+        "A nature collection entry is labelled \"{}\" and described as: \"{}\". Suggest {}-{} short English tags for it (lowercase, single words or short phrases) based only on that text. Return strict JSON {{\"tags\": [...]}} and nothing else.",
+        label, description, tag_min, tag_max
+    )
+}
+
+fn parse_retag_answer(text: &str) -> Result<Vec<String>, AppError> {
+    let json_text = extract_json(text).unwrap_or_else(|| text.to_string());
+    let value: serde_json::Value = serde_json::from_str(&json_text)
+        .map_err(|e| AppError::upstream(format!("Failed to parse retag JSON: {}", e)))?;
+    Ok(value
+        .get("tags")
+        .and_then(|tags| tags.as_array())
+        .ok_or_else(|| AppError::upstream("Retag answer has no tags array"))?
+        .iter()
+        .filter_map(|tag| tag.as_str().map(str::to_string))
+        .collect())
+}
+
+const CONFIDENCE_MAX_TOKENS: usize = 64;
+
+const CONFIDENCE_FOLLOW_UP: &str = "Your answer is missing confidence. Reply with strict JSON {\"confidence\": number between 0 and 1} and nothing else.";
+
+/// Follows up on an answer that left out `confidence`, asking for just that number.
+///
+/// Best effort: any failure keeps the confidence `null` rather than failing the upload.
+async fn ask_for_confidence(
+    state: &AppState,
+    options: &ClassifyOptions,
+    question: serde_json::Value,
+    answer: &str,
+) -> Option<f64> {
+    let body = serde_json::json!({
+        "model": options.model,
+        "max_tokens": CONFIDENCE_MAX_TOKENS,
+        "system": CLASSIFY_SYSTEM_PROMPT,
+        "messages": [
+            question,
+            { "role": "assistant", "content": answer },
+            { "role": "user", "content": CONFIDENCE_FOLLOW_UP }
+        ]
+    });
+
+    match send_anthropic(state, &body).await {
+        Ok(response) => parse_confidence_answer(response_text(&response)),
+        Err(err) => {
+            warn!("confidence follow-up failed: {}", err.message);
+            None
+        }
+    }
+}
 
-    let Some(row) = row else {
-        return Err(AppError::not_found("Entry not found"));
-    };
+fn parse_confidence_answer(text: &str) -> Option<f64> {
+    let json_text = extract_json(text).unwrap_or_else(|| text.to_string());
+    let confidence = serde_json::from_str::<serde_json::Value>(&json_text)
+        .ok()
+        .and_then(|value| value.get("confidence").and_then(|v| v.as_f64()))
+        .filter(|confidence| (0.0..=1.0).contains(confidence));
+    if confidence.is_none() {
+        warn!("confidence follow-up returned no usable confidence");
+    }
+    confidence
+}
 
-    let deleted_at: Option<DateTime<Utc>> = row.get("deleted_at");
-    let deleted_at = deleted_at.ok_or_else(|| AppError::bad_request("Entry not deleted"))?;
-    if Utc::now().signed_duration_since(deleted_at) > Duration::hours(1) {
-        return Err(AppError::bad_request("Restore window expired"));
+fn base64_len(len: usize) -> usize {
+    len.div_ceil(3) * 4
+}
+
+/// Longest edge Anthropic makes use of; larger images only cost more tokens.
+const ANTHROPIC_MAX_IMAGE_EDGE: u32 = 1568;
+
+/// The image to send to Anthropic: the stored bytes, or a downscaled JPEG copy when they are
+/// larger than Anthropic uses or over the size limit. The original on disk is never changed.
+fn anthropic_image<'a>(
+    state: &AppState,
+    bytes: &'a [u8],
+    mime: &'a str,
+    decoded: Option<&DynamicImage>,
+) -> Result<(std::borrow::Cow<'a, [u8]>, &'a str), AppError> {
+    let Some(img) = decoded else {
+        return Ok((std::borrow::Cow::Borrowed(bytes), mime));
+    };
+    if img.width().max(img.height()) > ANTHROPIC_MAX_IMAGE_EDGE {
+        let (resized, _) = prepare_for_classification(img, state.anthropic_image_quality)?;
+        return Ok((std::borrow::Cow::Owned(resized), "image/jpeg"));
     }
+    if base64_len(bytes.len()) > ANTHROPIC_MAX_IMAGE_BYTES {
+        return Ok((
+            std::borrow::Cow::Owned(shrink_for_anthropic(img, state.anthropic_image_quality)?),
+            "image/jpeg",
+        ));
+    }
+    Ok((std::borrow::Cow::Borrowed(bytes), mime))
+}
 
-    sqlx::query("UPDATE entries SET deleted_at = NULL WHERE id = $1")
-        .bind(id)
-        .execute(&state.db)
-        .await?;
+/// Resizes `img` to fit `ANTHROPIC_MAX_IMAGE_EDGE` and re-encodes it as JPEG, returning the
+/// bytes and their mime type; shrinks further if that is still over the size limit.
+fn prepare_for_classification(img: &DynamicImage, quality: u8) -> Result<(Vec<u8>, String), AppError> {
+    let resized = img.resize(
+        ANTHROPIC_MAX_IMAGE_EDGE,
+        ANTHROPIC_MAX_IMAGE_EDGE,
+        image::imageops::FilterType::Triangle,
+    );
+    Ok((shrink_for_anthropic(&resized, quality)?, "image/jpeg".to_string()))
+}
 
-    Ok(Json(serde_json::json!({ "status": "restored" })))
+/// Re-encodes an image as JPEG at `ANTHROPIC_IMAGE_QUALITY`, shrinking it until the base64
+/// payload fits.
+fn shrink_for_anthropic(img: &DynamicImage, quality: u8) -> Result<Vec<u8>, AppError> {
+    let mut current = DynamicImage::ImageRgb8(img.to_rgb8());
+    loop {
+        let mut out = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut out, quality)
+            .encode_image(&current)?;
+        let fits = base64_len(out.len()) <= ANTHROPIC_MAX_IMAGE_BYTES;
+        if fits || current.width().max(current.height()) <= 256 {
+            info!(
+                "downscaled image for Anthropic to {}x{} at quality {}: {} bytes",
+                current.width(),
+                current.height(),
+                quality,
+                out.len()
+            );
+            return Ok(out);
+        }
+        current = current.resize(
+            current.width() * 3 / 4,
+            current.height() * 3 / 4,
+            image::imageops::FilterType::Triangle,
+        );
+    }
 }
 
-async fn toggle_share(
-    State(state): State<Arc<AppState>>,
-    Path(id): Path<Uuid>,
-    Json(payload): Json<SharePayload>,
-) -> Result<Json<EntryDetail>, AppError> {
-    let share_token = if payload.enable {
-        Some(Uuid::new_v4().to_string())
-    } else {
-        None
-    };
+/// Base64-encodes an image for the Messages API, rejecting payloads Anthropic won't accept.
+fn encode_for_anthropic(bytes: &[u8]) -> Result<String, AppError> {
+    let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
+    info!(
+        "sending image to Anthropic: {} bytes original, {} bytes base64",
+        bytes.len(),
+        b64.len()
+    );
+    if b64.len() > ANTHROPIC_MAX_IMAGE_BYTES {
+        return Err(AppError::payload_too_large(format!(
+            "Image is too large to classify: {} bytes base64-encoded, Anthropic accepts at most {} bytes",
+            b64.len(),
+            ANTHROPIC_MAX_IMAGE_BYTES
+        )));
+    }
+    Ok(b64)
+}
 
-    sqlx::query("UPDATE entries SET share_token = $1 WHERE id = $2")
-        .bind(&share_token)
-        .bind(id)
-        .execute(&state.db)
-        .await?;
+/// Wait suggested to clients when Anthropic throttles without sending `Retry-After`.
+const ANTHROPIC_DEFAULT_RETRY_AFTER_SECS: u64 = 30;
 
-    let row = sqlx::query(
-        "SELECT id, created_at, image_path, label, description, confidence, tags, share_token \
-         FROM entries WHERE id = $1",
-    )
-    .bind(id)
-    .fetch_optional(&state.db)
-    .await?;
+/// Longest `retry-after` we wait out inside a request; longer waits are passed to the client.
+const ANTHROPIC_MAX_RETRY_WAIT_SECS: u64 = 20;
 
-    let row = row.ok_or_else(|| AppError::not_found("Entry not found"))?;
-    Ok(Json(entry_detail_from_row(row)))
+/// Why one model API attempt failed.
+enum UpstreamFailure {
+    /// Transport errors and 429/5xx/529: worth another attempt, after `retry_after` if given.
+    Retryable {
+        error: AppError,
+        retry_after: Option<u64>,
+    },
+    /// Anything else (bad request, auth, unparseable body): retrying won't help.
+    Fatal(AppError),
 }
 
-async fn classify_image(
+/// Posts a Messages API request and returns the decoded response body.
+///
+/// Retryable failures are retried up to `ANTHROPIC_MAX_RETRIES` times, waiting
+/// `ANTHROPIC_BACKOFF_MS` doubled per attempt plus jitter, or the `retry-after` Anthropic sent.
+async fn send_anthropic(
     state: &AppState,
-    bytes: &[u8],
-    mime: &str,
-) -> Result<Classification, AppError> {
-    let b64 = base64::engine::general_purpose::STANDARD.encode(bytes);
-
-    let prompt = "Identify the natural scene. Return strict JSON with fields: label (short name), description (1-2 sentences), tags (array of 3-6 lowercase words), confidence (0-1). No markdown.";
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, AppError> {
+    send_with_retries(state, "Anthropic", || send_anthropic_once(state, body)).await
+}
 
-    let body = serde_json::json!({
-        "model": state.anthropic_model,
-        "max_tokens": 512,
-        "system": "You are a friendly nature guide who classifies landscapes, plants, animals, and weather. Avoid brand names. Be concise.",
-        "messages": [
-            {
-                "role": "user",
-                "content": [
-                    {
-                        "type": "image",
-                        "source": {
-                            "type": "base64",
-                            "media_type": mime,
-                            "data": b64
-                        }
-                    },
-                    {
-                        "type": "text",
-                        "text": prompt
-                    }
-                ]
+/// Runs `send` until it succeeds or fails for good, with the backoff described on
+/// `send_anthropic`; `provider` names the API in logs.
+async fn send_with_retries<F, Fut>(
+    state: &AppState,
+    provider: &str,
+    send: F,
+) -> Result<serde_json::Value, AppError>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<serde_json::Value, UpstreamFailure>>,
+{
+    let mut attempt = 0;
+    loop {
+        let (error, retry_after) = match send().await {
+            Ok(response) => return Ok(response),
+            Err(UpstreamFailure::Fatal(error)) => return Err(error),
+            Err(UpstreamFailure::Retryable { error, retry_after }) => (error, retry_after),
+        };
+        if attempt >= state.anthropic_max_retries {
+            return Err(error);
+        }
+        let delay = match retry_after {
+            Some(secs) if secs > ANTHROPIC_MAX_RETRY_WAIT_SECS => return Err(error),
+            Some(secs) => std::time::Duration::from_secs(secs),
+            None => {
+                let base = state.anthropic_backoff_ms.saturating_mul(1 << attempt.min(16));
+                // Up to half the delay again, so concurrent uploads don't retry in lockstep.
+                let jitter = (Uuid::new_v4().as_u128() % (base / 2 + 1) as u128) as u64;
+                std::time::Duration::from_millis(base + jitter)
             }
-        ]
-    });
+        };
+        attempt += 1;
+        warn!(
+            "{} attempt {} failed ({}); retrying in {}ms",
+            provider,
+            attempt,
+            error.message,
+            delay.as_millis()
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
 
-    let client = reqwest::Client::new();
-    let res = client
+async fn send_anthropic_once(
+    state: &AppState,
+    body: &serde_json::Value,
+) -> Result<serde_json::Value, UpstreamFailure> {
+    let res = state
+        .http
         .post("https://api.anthropic.com/v1/messages")
         .header("x-api-key", &state.anthropic_key)
         .header("anthropic-version", "2023-06-01")
-        .json(&body)
+        .json(body)
         .send()
         .await
-        .map_err(|e| AppError::upstream(format!("Failed to reach Anthropic: {}", e)))?;
+        .map_err(|e| UpstreamFailure::Retryable {
+            error: AppError::upstream(format!("Failed to reach Anthropic: {}", e)),
+            retry_after: None,
+        })?;
 
     if !res.status().is_success() {
         let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok());
         let text = res.text().await.unwrap_or_default();
-        return Err(AppError::upstream(format!(
-            "Anthropic error {}: {}",
-            status, text
-        )));
+        let client_wait = retry_after.unwrap_or(ANTHROPIC_DEFAULT_RETRY_AFTER_SECS);
+        // Upstream throttling is passed on so clients can back off instead of seeing a 502.
+        let error = match status.as_u16() {
+            429 => AppError::throttled(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("Anthropic rate limit reached: {}", text),
+                client_wait,
+            ),
+            529 => AppError::throttled(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("Anthropic is overloaded: {}", text),
+                client_wait,
+            ),
+            _ => AppError::upstream(format!("Anthropic error {}: {}", status, text)),
+        };
+        return Err(match status.as_u16() {
+            429 | 500 | 502 | 503 | 529 => UpstreamFailure::Retryable { error, retry_after },
+            _ => UpstreamFailure::Fatal(error),
+        });
     }
 
-    let value: serde_json::Value = res.json().await.map_err(|e| {
-        AppError::upstream(format!("Failed to parse Anthropic response: {}", e))
-    })?;
+    res.json().await.map_err(|e| {
+        UpstreamFailure::Fatal(AppError::upstream(format!(
+            "Failed to parse Anthropic response: {}",
+            e
+        )))
+    })
+}
 
-    let text = value
+/// The first text block of a Messages API response, or `""` if there is none.
+fn response_text(response: &serde_json::Value) -> &str {
+    response
         .get("content")
         .and_then(|v| v.as_array())
         .and_then(|arr| {
@@ -491,64 +6969,568 @@ async fn classify_image(
         })
         .and_then(|item| item.get("text"))
         .and_then(|v| v.as_str())
-        .unwrap_or("");
+        .unwrap_or("")
+}
+
+/// Asks the model whether an upload is acceptable, using the operator's `MODERATION_PROMPT`.
+///
+/// Rejects when the model disallows the image or scores it at or above the threshold.
+async fn moderate_image(
+    state: &AppState,
+    moderation: &ModerationConfig,
+    bytes: &[u8],
+    mime: &str,
+) -> Result<(), AppError> {
+    let verdict = state.classifier.moderate(state, moderation, bytes, mime).await?;
+
+    let flagged = verdict.score.is_some_and(|score| score >= moderation.threshold);
+    if !verdict.allowed || flagged {
+        return Err(AppError::unprocessable(format!(
+            "Image rejected by moderation: {}",
+            verdict.reason.unwrap_or_else(|| "no reason given".to_string())
+        )));
+    }
+    Ok(())
+}
 
+const MODERATION_QUESTION: &str = "Return strict JSON with fields: allowed (boolean), score (0-1, how likely the image violates the policy), reason (short). No markdown.";
+
+fn parse_moderation_answer(text: &str) -> Result<ModerationVerdict, AppError> {
     let json_text = extract_json(text).unwrap_or_else(|| text.to_string());
-    let parsed: Classification = serde_json::from_str(&json_text).map_err(|e| {
-        AppError::upstream(format!("Failed to parse classification JSON: {}", e))
-    })?;
+    serde_json::from_str(&json_text)
+        .map_err(|e| AppError::upstream(format!("Failed to parse moderation JSON: {}", e)))
+}
 
-    Ok(parsed)
+/// Keywords per category used by `CATEGORY_VALIDATION` unless `CATEGORY_RULES` overrides them.
+const DEFAULT_CATEGORY_RULES: &str = "animal=animal|bird|mammal|insect|reptile|amphibian|fish|spider;\
+plant=plant|tree|flower|shrub|grass|fern|moss|leaf;\
+fungus=fungus|mushroom|mold|lichen;\
+landscape=landscape|mountain|river|lake|beach|forest|desert|valley|rock formation|canyon;\
+weather=weather|cloud|rain|snow|storm|fog|rainbow|lightning";
+
+/// Parses `CATEGORY_RULES` (`animal=bird|mammal;plant=tree|flower`).
+fn parse_category_rules(value: &str) -> anyhow::Result<HashMap<String, Vec<String>>> {
+    let mut rules = HashMap::new();
+    for rule in value.split(';').map(str::trim).filter(|rule| !rule.is_empty()) {
+        let (category, keywords) = rule
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid CATEGORY_RULES entry '{}': expected category=keyword|keyword", rule))?;
+        let keywords: Vec<String> = keywords
+            .split('|')
+            .map(|keyword| keyword.trim().to_lowercase())
+            .filter(|keyword| !keyword.is_empty())
+            .collect();
+        if keywords.is_empty() {
+            anyhow::bail!("CATEGORY_RULES entry '{}' has no keywords", rule);
+        }
+        rules.insert(category.trim().to_lowercase(), keywords);
+    }
+    Ok(rules)
+}
+
+/// Whether the model's `category` extra field contradicts its own label and tags.
+///
+/// Advisory only: flags when the label or tags match another category's keywords and none
+/// of the claimed category's. Unknown or missing categories are never flagged.
+fn category_mismatch(state: &AppState, classification: &Classification) -> bool {
+    let Some(rules) = &state.category_rules else {
+        return false;
+    };
+    let Some(category) = classification
+        .extra
+        .get("category")
+        .and_then(|value| value.as_str())
+        .map(|value| value.trim().to_lowercase())
+    else {
+        return false;
+    };
+    let Some(own_keywords) = rules.get(&category) else {
+        return false;
+    };
+
+    let terms: Vec<String> = std::iter::once(&classification.label)
+        .chain(&classification.tags)
+        .map(|term| term.to_lowercase())
+        .collect();
+    let mentions = |keywords: &[String]| {
+        terms.iter().any(|term| {
+            keywords.iter().any(|keyword| {
+                if keyword.contains(' ') {
+                    term.contains(keyword.as_str())
+                } else {
+                    term.split(|c: char| !c.is_alphanumeric()).any(|word| word == keyword)
+                }
+            })
+        })
+    };
+
+    if mentions(own_keywords) {
+        return false;
+    }
+    rules
+        .iter()
+        .any(|(other, keywords)| *other != category && mentions(keywords))
+}
+
+/// Keeps only the configured extra fields, accepting them inside `extra` or at the top level.
+fn collect_extra_fields(
+    state: &AppState,
+    value: &serde_json::Value,
+) -> serde_json::Map<String, serde_json::Value> {
+    state
+        .extra_fields
+        .iter()
+        .filter_map(|field| {
+            let found = value
+                .get("extra")
+                .and_then(|extra| extra.get(&field.name))
+                .or_else(|| value.get(&field.name))?;
+            Some((field.name.clone(), found.clone()))
+        })
+        .collect()
+}
+
+/// Most alternatives kept per entry.
+const MAX_ALTERNATIVES: usize = 5;
+
+/// Reads the model's `alternatives`, skipping malformed items and repeats of the primary label.
+fn collect_alternatives(label: &str, value: &serde_json::Value) -> Vec<Alternative> {
+    let Some(items) = value.get("alternatives").and_then(|v| v.as_array()) else {
+        return Vec::new();
+    };
+    let mut alternatives: Vec<Alternative> = Vec::new();
+    for item in items {
+        let (alt_label, confidence) = match item {
+            serde_json::Value::String(alt_label) => (alt_label.as_str(), None),
+            serde_json::Value::Object(fields) => match fields.get("label").and_then(|v| v.as_str()) {
+                Some(alt_label) => (
+                    alt_label,
+                    fields
+                        .get("confidence")
+                        .and_then(|v| v.as_f64())
+                        .filter(|confidence| (0.0..=1.0).contains(confidence)),
+                ),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let alt_label = alt_label.trim();
+        if alt_label.is_empty()
+            || alt_label.eq_ignore_ascii_case(label.trim())
+            || alternatives
+                .iter()
+                .any(|existing| existing.label.eq_ignore_ascii_case(alt_label))
+        {
+            continue;
+        }
+        alternatives.push(Alternative {
+            label: alt_label.to_string(),
+            confidence,
+        });
+        if alternatives.len() == MAX_ALTERNATIVES {
+            break;
+        }
+    }
+    alternatives
 }
 
+/// The first balanced JSON object in a model answer, looking inside a markdown code
+/// fence first. Braces inside string literals don't count, so prose around the object
+/// or a `}` in the description doesn't cut it short. `None` when nothing balances.
 fn extract_json(text: &str) -> Option<String> {
-    let start = text.find('{')?;
-    let end = text.rfind('}')?;
-    if start >= end {
-        return None;
+    fenced_block(text)
+        .and_then(balanced_object)
+        .or_else(|| balanced_object(text))
+        .map(str::to_string)
+}
+
+/// The body of the first markdown code fence, minus its language tag; runs to the end
+/// of the text when the closing fence is missing.
+fn fenced_block(text: &str) -> Option<&str> {
+    let (_, rest) = text.split_once("```")?;
+    let body = match rest.split_once('\n') {
+        Some((tag, body)) if !tag.contains('{') => body,
+        _ => rest,
+    };
+    Some(body.split_once("```").map_or(body, |(body, _)| body))
+}
+
+/// The first `{...}` span with balanced braces that parses as JSON, or else the first
+/// balanced span at all, so a caller can still report why it doesn't parse.
+fn balanced_object(text: &str) -> Option<&str> {
+    let mut first = None;
+    'starts: for (start, _) in text.char_indices().filter(|(_, ch)| *ch == '{') {
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        for (offset, ch) in text[start..].char_indices() {
+            if in_string {
+                match ch {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match ch {
+                '"' => in_string = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let candidate = &text[start..=start + offset];
+                        if serde_json::from_str::<serde_json::Value>(candidate).is_ok() {
+                            return Some(candidate);
+                        }
+                        first.get_or_insert(candidate);
+                        continue 'starts;
+                    }
+                }
+                _ => {}
+            }
+        }
     }
-    Some(text[start..=end].to_string())
+    first
+}
+
+/// Seconds between cleanup ticks.
+const CLEANUP_INTERVAL_SECS: u64 = 600;
+
+/// What the cleanup task last did, for `GET /api/admin/cleanup/status`.
+#[derive(Clone, Default, Serialize)]
+struct CleanupStatus {
+    started_at: Option<DateTime<Utc>>,
+    /// Last time the task woke up, whether or not it ran; goes stale if the task dies.
+    last_tick: Option<DateTime<Utc>>,
+    /// Last time a purge pass ran (ticks outside `CLEANUP_HOURS` don't count).
+    last_run: Option<DateTime<Utc>>,
+    last_purged: usize,
+    last_error: Option<String>,
 }
 
 fn spawn_cleanup(state: Arc<AppState>) {
+    state.cleanup_status.lock().unwrap().started_at = Some(Utc::now());
     tokio::spawn(async move {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(600));
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(CLEANUP_INTERVAL_SECS));
         loop {
             interval.tick().await;
-            if let Err(err) = cleanup_deleted(&state).await {
+            state.cleanup_status.lock().unwrap().last_tick = Some(Utc::now());
+            if let Some(hours) = state.cleanup_hours
+                && !hours.contains(Utc::now().hour())
+            {
+                continue;
+            }
+            let result = cleanup_deleted(&state).await;
+            {
+                let mut status = state.cleanup_status.lock().unwrap();
+                status.last_run = Some(Utc::now());
+                match &result {
+                    Ok(purged) => {
+                        status.last_purged = *purged;
+                        status.last_error = None;
+                    }
+                    Err(err) => {
+                        status.last_purged = 0;
+                        status.last_error = Some(err.message.clone());
+                    }
+                }
+            }
+            if let Err(err) = result {
                 error!("cleanup failed: {}", err);
             }
+            if let Some(days) = state.raw_json_retention_days
+                && let Err(err) = strip_raw_json(&state, days).await
+            {
+                error!("raw_json retention failed: {}", err);
+            }
         }
     });
 }
 
-async fn cleanup_deleted(state: &AppState) -> Result<(), AppError> {
-    let cutoff = Utc::now() - Duration::hours(1);
+/// UTC hours the cleanup may run in, from `CLEANUP_HOURS` (`start-end`, end exclusive).
+#[derive(Clone, Copy)]
+struct HourWindow {
+    start: u32,
+    end: u32,
+}
+
+impl HourWindow {
+    /// Windows may wrap past midnight, e.g. `22-6`.
+    fn contains(&self, hour: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&hour)
+        } else {
+            hour >= self.start || hour < self.end
+        }
+    }
+}
+
+fn parse_hour_window(var: &str, value: &str) -> anyhow::Result<HourWindow> {
+    let invalid = || anyhow::anyhow!("{} must look like 1-5 (UTC hours 0-24, end exclusive)", var);
+    let (start, end) = value.trim().split_once('-').ok_or_else(invalid)?;
+    let start: u32 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u32 = end.trim().parse().map_err(|_| invalid())?;
+    if start > 23 || end > 24 || start == end {
+        return Err(invalid());
+    }
+    Ok(HourWindow { start, end })
+}
+
+/// Purges entries soft-deleted longer than `RESTORE_WINDOW_MINUTES` ago, oldest first and at most
+/// `CLEANUP_BATCH_SIZE` per pass; the rest wait for the next tick. Returns how many it purged.
+async fn cleanup_deleted(state: &AppState) -> Result<usize, AppError> {
+    let cutoff = Utc::now() - state.restore_window;
+    // Files and webhooks follow the rows the DELETE actually removed, so a failed DELETE
+    // (or an entry restored in the meantime) is never reported as purged.
+    let rows = sqlx::query(
+        "WITH purged AS ( \
+             DELETE FROM entries WHERE id IN ( \
+                 SELECT id FROM entries \
+                 WHERE deleted_at IS NOT NULL AND deleted_at < $1 ORDER BY deleted_at LIMIT $2 \
+             ) AND deleted_at IS NOT NULL \
+             RETURNING id, image_path, thumb_path, delete_reason \
+         ), tombstones AS ( \
+             INSERT INTO entry_tombstones (id) SELECT id FROM purged ON CONFLICT DO NOTHING \
+         ) \
+         SELECT * FROM purged",
+    )
+    .bind(cutoff)
+    .bind(state.cleanup_batch_size.map(|size| size as i64))
+    .fetch_all(&state.db)
+    .await?;
+
+    for row in &rows {
+        let id: Uuid = row.get("id");
+        let delete_reason: Option<String> = row.get("delete_reason");
+        remove_entry_files(state, row.get("image_path"), row.get("thumb_path")).await;
+        notify_webhook(
+            state,
+            "entry.purged",
+            id,
+            delete_reason.as_deref().unwrap_or("unknown"),
+        );
+    }
+    if !rows.is_empty() {
+        info!("cleanup purged {} entries", rows.len());
+    }
+
+    Ok(rows.len())
+}
+
+#[derive(Serialize)]
+struct CleanupStatusResponse {
+    #[serde(flatten)]
+    status: CleanupStatus,
+    /// When the task is due to wake up next; it skips the pass outside `CLEANUP_HOURS`.
+    next_tick: Option<DateTime<Utc>>,
+    /// False once the task has missed two ticks in a row, e.g. because it died.
+    alive: bool,
+}
+
+async fn get_cleanup_status(State(state): State<Arc<AppState>>) -> Json<CleanupStatusResponse> {
+    let status = state.cleanup_status.lock().unwrap().clone();
+    let interval = Duration::seconds(CLEANUP_INTERVAL_SECS as i64);
+    let last_seen = status.last_tick.or(status.started_at);
+    let next_tick = status.last_tick.map(|tick| tick + interval);
+    let alive = last_seen.is_some_and(|seen| Utc::now() - seen < interval * 2);
+    Json(CleanupStatusResponse {
+        status,
+        next_tick,
+        alive,
+    })
+}
+
+#[derive(Serialize)]
+struct CleanupPreview {
+    /// Entries deleted before this are past the restore window.
+    cutoff: DateTime<Utc>,
+    /// Deleted entries past the restore window.
+    eligible: i64,
+    /// How many of them the next pass would purge, given `CLEANUP_BATCH_SIZE`.
+    next_pass: usize,
+    /// On-disk size of the eligible entries' images and thumbnails.
+    image_bytes: u64,
+    thumb_bytes: u64,
+    /// Files the rows point to that are already gone.
+    missing_files: usize,
+}
+
+/// Dry run of `cleanup_deleted`: what the cleanup would remove right now, without removing it.
+async fn preview_cleanup(State(state): State<Arc<AppState>>) -> Result<Json<CleanupPreview>, AppError> {
+    let cutoff = Utc::now() - state.restore_window;
     let rows = sqlx::query(
-        "SELECT id, image_path FROM entries WHERE deleted_at IS NOT NULL AND deleted_at < $1",
+        "SELECT image_path, thumb_path FROM entries \
+         WHERE deleted_at IS NOT NULL AND deleted_at < $1 ORDER BY deleted_at",
     )
     .bind(cutoff)
     .fetch_all(&state.db)
     .await?;
 
+    let mut preview = CleanupPreview {
+        cutoff,
+        eligible: rows.len() as i64,
+        next_pass: state
+            .cleanup_batch_size
+            .map_or(rows.len(), |size| size.min(rows.len())),
+        image_bytes: 0,
+        thumb_bytes: 0,
+        missing_files: 0,
+    };
     for row in rows {
         let image_path: String = row.get("image_path");
-        let file_path = state.storage_dir.join(&image_path);
-        if let Err(err) = tokio::fs::remove_file(&file_path).await {
-            error!("failed to remove image {}: {}", image_path, err);
+        let thumb_path: Option<String> = row.get("thumb_path");
+        match tokio::fs::metadata(state.storage_dir.join(&image_path)).await {
+            Ok(meta) => preview.image_bytes += meta.len(),
+            Err(_) => preview.missing_files += 1,
+        }
+        if let Some(thumb_path) = thumb_path {
+            match tokio::fs::metadata(state.storage_dir.join(&thumb_path)).await {
+                Ok(meta) => preview.thumb_bytes += meta.len(),
+                Err(_) => preview.missing_files += 1,
+            }
         }
     }
+    Ok(Json(preview))
+}
 
-    sqlx::query(
-        "DELETE FROM entries WHERE deleted_at IS NOT NULL AND deleted_at < $1",
+/// Drops the stored model output of entries older than `RAW_JSON_RETENTION_DAYS`.
+async fn strip_raw_json(state: &AppState, days: usize) -> Result<(), AppError> {
+    let result = sqlx::query(
+        "UPDATE entries SET raw_json = NULL \
+         WHERE raw_json IS NOT NULL AND created_at < NOW() - make_interval(days => $1)",
     )
-    .bind(cutoff)
+    .bind(days as i32)
     .execute(&state.db)
     .await?;
-
+    if result.rows_affected() > 0 {
+        info!("stripped raw_json from {} entries", result.rows_affected());
+    }
     Ok(())
 }
 
+/// Deletes an entry's image and thumbnail from disk, returning how many files were removed.
+async fn remove_entry_files(state: &AppState, image_path: String, thumb_path: Option<String>) -> usize {
+    let mut removed = 0;
+    for path in std::iter::once(image_path).chain(thumb_path) {
+        let file_path = state.storage_dir.join(&path);
+        match tokio::fs::remove_file(&file_path).await {
+            Ok(()) => removed += 1,
+            Err(err) => error!("failed to remove image {}: {}", path, err),
+        }
+    }
+    removed
+}
+
+/// Text the reset request must carry in `confirm`.
+const RESET_CONFIRMATION: &str = "delete all entries";
+
+#[derive(Deserialize)]
+struct ResetPayload {
+    confirm: String,
+}
+
+#[derive(Serialize)]
+struct ResetResponse {
+    entries: u64,
+    files: usize,
+    feedback: u64,
+    tag_colors: u64,
+}
+
+/// Hard-deletes every entry (trash included) with its files, feedback and tag colors,
+/// and puts settings back to their defaults. Disabled unless `ADMIN_ALLOW_RESET` is on.
+async fn reset_collection(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ResetPayload>,
+) -> Result<Json<ResetResponse>, AppError> {
+    if !state.allow_reset {
+        return Err(AppError::forbidden("Reset is disabled; set ADMIN_ALLOW_RESET=true to enable it"));
+    }
+    if payload.confirm != RESET_CONFIRMATION {
+        return Err(AppError::bad_request(format!(
+            "confirm must be exactly \"{}\"",
+            RESET_CONFIRMATION
+        )));
+    }
+
+    // Rows go first, in one transaction; files are only removed once that has committed.
+    let mut tx = state.db.begin().await?;
+    let rows = sqlx::query(
+        "WITH removed AS (DELETE FROM entries RETURNING id, image_path, thumb_path), \
+         tombstones AS ( \
+             INSERT INTO entry_tombstones (id) SELECT id FROM removed ON CONFLICT DO NOTHING \
+         ) \
+         SELECT * FROM removed",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    let feedback = sqlx::query("DELETE FROM classification_feedback")
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let tag_colors = sqlx::query("DELETE FROM tag_colors")
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query(
+        "UPDATE settings SET is_public = FALSE, auto_share = FALSE, empty_message = NULL, \
+         updated_at = NOW() WHERE id = 1",
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let mut files = 0;
+    for row in &rows {
+        files += remove_entry_files(&state, row.get("image_path"), row.get("thumb_path")).await;
+    }
+    warn!(
+        "collection reset: {} entries, {} files, {} feedback rows, {} tag colors removed",
+        rows.len(),
+        files,
+        feedback,
+        tag_colors
+    );
+
+    Ok(Json(ResetResponse {
+        entries: rows.len() as u64,
+        files,
+        feedback,
+        tag_colors,
+    }))
+}
+
+/// Posts `event` to the configured webhook in the background if it is subscribed.
+/// `reason` is why the entry was deleted (`user`, ...), which purges carry over.
+fn notify_webhook(state: &AppState, event: &'static str, entry_id: Uuid, reason: &str) {
+    let Some(webhook) = &state.webhook else {
+        return;
+    };
+    if !webhook.events.iter().any(|subscribed| subscribed == event) {
+        return;
+    }
+
+    let client = state.http.clone();
+    let url = webhook.url.clone();
+    let body = serde_json::json!({
+        "event": event,
+        "entry_id": entry_id,
+        "reason": reason,
+        "occurred_at": Utc::now(),
+    });
+    tokio::spawn(async move {
+        match client.post(&url).json(&body).send().await {
+            Ok(res) if !res.status().is_success() => {
+                error!("webhook {} for {} returned {}", event, entry_id, res.status());
+            }
+            Ok(_) => {}
+            Err(err) => error!("webhook {} for {} failed: {}", event, entry_id, err),
+        }
+    });
+}
+
 async fn ensure_settings(db: &PgPool) -> Result<(), sqlx::Error> {
     sqlx::query(
         "INSERT INTO settings (id, is_public) VALUES (1, FALSE) ON CONFLICT (id) DO NOTHING",
@@ -562,6 +7544,8 @@ async fn ensure_settings(db: &PgPool) -> Result<(), sqlx::Error> {
 struct AppError {
     status: StatusCode,
     message: String,
+    /// Seconds a throttled client should wait; sent as `Retry-After` and `retry_after_secs`.
+    retry_after: Option<u64>,
 }
 
 impl std::fmt::Display for AppError {
@@ -575,6 +7559,15 @@ impl AppError {
         Self {
             status: StatusCode::BAD_REQUEST,
             message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn unauthorized(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            message: message.into(),
+            retry_after: None,
         }
     }
 
@@ -582,6 +7575,57 @@ impl AppError {
         Self {
             status: StatusCode::NOT_FOUND,
             message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn internal(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn payload_too_large(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::PAYLOAD_TOO_LARGE,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn unprocessable(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn forbidden(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn gateway_timeout(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::GATEWAY_TIMEOUT,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// A throttling response (`429`, or `503` when upstream is overloaded) with the wait
+    /// reported by whichever limit tripped.
+    fn throttled(status: StatusCode, message: impl Into<String>, retry_after_secs: u64) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            retry_after: Some(retry_after_secs),
         }
     }
 
@@ -589,6 +7633,7 @@ impl AppError {
         Self {
             status: StatusCode::BAD_GATEWAY,
             message: message.into(),
+            retry_after: None,
         }
     }
 }
@@ -598,6 +7643,7 @@ impl From<sqlx::Error> for AppError {
         AppError {
             status: StatusCode::INTERNAL_SERVER_ERROR,
             message: err.to_string(),
+            retry_after: None,
         }
     }
 }
@@ -607,6 +7653,7 @@ impl From<std::io::Error> for AppError {
         AppError {
             status: StatusCode::INTERNAL_SERVER_ERROR,
             message: err.to_string(),
+            retry_after: None,
         }
     }
 }
@@ -616,6 +7663,21 @@ impl From<serde_json::Error> for AppError {
         AppError {
             status: StatusCode::INTERNAL_SERVER_ERROR,
             message: err.to_string(),
+            retry_after: None,
+        }
+    }
+}
+
+impl From<image::ImageError> for AppError {
+    fn from(err: image::ImageError) -> Self {
+        let status = match err {
+            image::ImageError::Limits(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        AppError {
+            status,
+            message: err.to_string(),
+            retry_after: None,
         }
     }
 }
@@ -625,15 +7687,28 @@ impl From<axum::extract::multipart::MultipartError> for AppError {
         AppError {
             status: StatusCode::BAD_REQUEST,
             message: err.to_string(),
+            retry_after: None,
         }
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let Some(retry_after) = self.retry_after else {
+            let body = Json(serde_json::json!({
+                "error": self.message,
+            }));
+            return (self.status, body).into_response();
+        };
         let body = Json(serde_json::json!({
             "error": self.message,
+            "retry_after_secs": retry_after,
         }));
-        (self.status, body).into_response()
+        (
+            self.status,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            body,
+        )
+            .into_response()
     }
 }
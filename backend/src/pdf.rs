@@ -0,0 +1,262 @@
+//! Minimal PDF writer for the printable field guide.
+//!
+//! Only what the guide needs: pages with text in the standard Helvetica faces and
+//! JPEG images. The standard fonts need no embedding, and JPEGs can be stored as-is
+//! with `DCTDecode`, which keeps this small enough to not need a dependency. Pages are
+//! written out one at a time, so the guide can be streamed.
+
+use std::fmt::Write as _;
+
+/// A4 in points.
+pub const PAGE_WIDTH: f32 = 595.0;
+pub const PAGE_HEIGHT: f32 = 842.0;
+
+#[derive(Clone, Copy)]
+pub enum Font {
+    Regular,
+    Bold,
+    Italic,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+        }
+    }
+}
+
+/// A baseline JPEG in the RGB color space.
+pub struct JpegImage {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Default)]
+pub struct Page {
+    content: String,
+    images: Vec<JpegImage>,
+}
+
+impl Page {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draws one line of text with its baseline at `y` (points from the bottom edge).
+    pub fn text(&mut self, font: Font, size: f32, x: f32, y: f32, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /{} {} Tf {} {} Td ({}) Tj ET",
+            font.resource(),
+            size,
+            x,
+            y,
+            encode_text(text)
+        );
+    }
+
+    /// Draws `image` scaled into the box whose lower-left corner is at `x`, `y`.
+    pub fn image(&mut self, image: JpegImage, x: f32, y: f32, width: f32, height: f32) {
+        let name = format!("Im{}", self.images.len() + 1);
+        let _ = writeln!(
+            self.content,
+            "q {} 0 0 {} {} {} cm /{} Do Q",
+            width, height, x, y, name
+        );
+        self.images.push(image);
+    }
+}
+
+/// Writes a document incrementally: `start`, then each `page` as it is ready, then
+/// `finish`, each returning the next bytes of the file. Only the page tree and the
+/// cross-reference table, both tiny, wait for the end, so pages can be sent as they
+/// are built instead of holding the whole document.
+pub struct PdfWriter {
+    /// Bytes handed out so far; where the next object starts.
+    written: usize,
+    /// File offset of each object 1..=n; object 1 is the catalog and 2 the page tree,
+    /// which is only written by `finish`.
+    offsets: Vec<usize>,
+    pages: Vec<usize>,
+}
+
+impl PdfWriter {
+    /// The writer, and the file header with the catalog and fonts.
+    pub fn start() -> (Self, Vec<u8>) {
+        let mut writer = Self {
+            written: 0,
+            offsets: Vec::new(),
+            pages: Vec::new(),
+        };
+        // The binary comment line tells tools the file contains 8-bit data.
+        let mut buf = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        writer.written = buf.len();
+        writer.object(&mut buf, b"<< /Type /Catalog /Pages 2 0 R >>");
+        // Reserved for the page tree.
+        writer.offsets.push(0);
+        for base in ["Helvetica", "Helvetica-Bold", "Helvetica-Oblique"] {
+            let font = format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                base
+            );
+            writer.object(&mut buf, font.as_bytes());
+        }
+        (writer, buf)
+    }
+
+    /// The objects of one page: its images, content stream and page dictionary.
+    pub fn page(&mut self, page: Page) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut xobjects = String::new();
+        for (index, image) in page.images.into_iter().enumerate() {
+            let mut body = format!(
+                "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+                 /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+                image.width,
+                image.height,
+                image.data.len()
+            )
+            .into_bytes();
+            body.extend_from_slice(&image.data);
+            body.extend_from_slice(b"\nendstream");
+            let id = self.object(&mut buf, &body);
+            let _ = write!(xobjects, "/Im{} {} 0 R ", index + 1, id);
+        }
+
+        let mut contents = format!("<< /Length {} >>\nstream\n", page.content.len()).into_bytes();
+        contents.extend_from_slice(page.content.as_bytes());
+        contents.extend_from_slice(b"endstream");
+        let contents_id = self.object(&mut buf, &contents);
+
+        let page_id = self.object(
+            &mut buf,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents {} 0 R \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R >> /XObject << {}>> >> >>",
+                PAGE_WIDTH, PAGE_HEIGHT, contents_id, xobjects
+            )
+            .as_bytes(),
+        );
+        self.pages.push(page_id);
+        buf
+    }
+
+    /// The page tree, cross-reference table and trailer that end the file.
+    pub fn finish(mut self) -> Vec<u8> {
+        let kids: Vec<String> = self.pages.iter().map(|id| format!("{} 0 R", id)).collect();
+        let tree = format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            self.pages.len()
+        );
+        let mut buf = Vec::new();
+        self.offsets[1] = self.written;
+        self.write_object(&mut buf, 2, tree.as_bytes());
+
+        let xref_offset = self.written;
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = writeln!(xref, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            xref,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            xref_offset
+        );
+        buf.extend_from_slice(xref.as_bytes());
+        buf
+    }
+
+    /// Appends a new object to `buf` and returns its id.
+    fn object(&mut self, buf: &mut Vec<u8>, body: &[u8]) -> usize {
+        self.offsets.push(self.written);
+        let id = self.offsets.len();
+        self.write_object(buf, id, body);
+        id
+    }
+
+    fn write_object(&mut self, buf: &mut Vec<u8>, id: usize, body: &[u8]) {
+        let start = buf.len();
+        buf.extend_from_slice(format!("{} 0 obj\n", id).as_bytes());
+        buf.extend_from_slice(body);
+        buf.extend_from_slice(b"\nendobj\n");
+        self.written += buf.len() - start;
+    }
+}
+
+/// Splits `text` into lines no wider than `max_width` points, breaking at spaces.
+pub fn wrap(text: &str, font: Font, size: f32, max_width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+        if !line.is_empty() && text_width(&candidate, font, size) > max_width {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Approximate rendered width using Helvetica's metrics; bold runs a little wider.
+pub fn text_width(text: &str, font: Font, size: f32) -> f32 {
+    let units: u32 = text.chars().map(char_width).sum();
+    let scale = match font {
+        Font::Bold => 1.06,
+        Font::Regular | Font::Italic => 1.0,
+    };
+    units as f32 * size / 1000.0 * scale
+}
+
+/// Helvetica advance widths for printable ASCII, in 1/1000 em.
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // space../
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // 0..?
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // @..O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // P.._
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // `..o
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // p..~
+];
+
+fn char_width(ch: char) -> u32 {
+    match ch as u32 {
+        code @ 32..=126 => HELVETICA_WIDTHS[(code - 32) as usize] as u32,
+        _ => 556,
+    }
+}
+
+/// Escapes a string for a PDF literal in WinAnsi; characters outside Latin-1 become `?`.
+fn encode_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            ' '..='~' => out.push(ch),
+            '\u{2018}' | '\u{2019}' => out.push('\''),
+            '\u{201C}' | '\u{201D}' => out.push('"'),
+            '\u{2013}' | '\u{2014}' => out.push('-'),
+            '\u{2026}' => out.push_str("..."),
+            '\u{A0}'..='\u{FF}' => {
+                let _ = write!(out, "\\{:03o}", ch as u32);
+            }
+            _ => out.push('?'),
+        }
+    }
+    out
+}
@@ -0,0 +1,1057 @@
+//! Backend tests. The route-level ones run against a real Postgres: `#[sqlx::test]`
+//! creates a fresh database per test from `DATABASE_URL` and runs the migrations on it.
+//! Classification goes through `MockClassifier`, so no API key or network access is needed.
+
+use super::*;
+use axum::http::Request;
+use tower::ServiceExt;
+
+const BOUNDARY: &str = "naturadex-test-boundary";
+
+/// Defaults matching an unconfigured deployment, with `CLASSIFIER=mock` and a scratch
+/// storage directory.
+async fn test_state(db: PgPool) -> AppState {
+    ensure_settings(&db).await.unwrap();
+    let storage_dir = std::env::temp_dir().join(format!("naturadex-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(storage_dir.join("images")).unwrap();
+    AppState {
+        db,
+        http: reqwest::Client::new(),
+        import_http: import_client().unwrap(),
+        storage_dir,
+        anthropic_key: String::new(),
+        anthropic_model: "claude-opus-4-5".to_string(),
+        classifier: Arc::new(MockClassifier),
+        model_allowlist: Vec::new(),
+        default_tags: Vec::new(),
+        extra_fields: Vec::new(),
+        prompt_template: PromptTemplate::parse(DEFAULT_CLASSIFICATION_PROMPT).unwrap(),
+        title_template: Template::parse("{label}").unwrap(),
+        prompt_tag_range: (3, 6),
+        prompt_categories: String::new(),
+        collapse_plural_tags: false,
+        generate_tags: true,
+        few_shot_examples: Vec::new(),
+        tag_synonyms: HashMap::new(),
+        tag_translations: HashMap::new(),
+        category_rules: None,
+        description_max_sentences: None,
+        description_max_chars: None,
+        public_base_url: None,
+        thumb_max_dim: 320,
+        thumb_format: ImageFormat::Jpeg,
+        reencode_format: None,
+        strip_exif: true,
+        webhook: None,
+        moderation: None,
+        write_token: None,
+        create_entry_timeout: std::time::Duration::from_secs(30),
+        anthropic_max_retries: 0,
+        anthropic_backoff_ms: 0,
+        batch_max_images: 20,
+        batch_images_per_call: 4,
+        default_page_size: 50,
+        max_page_size: 200,
+        choose_label_strict: false,
+        cleanup_batch_size: None,
+        cleanup_hours: None,
+        restore_window: Duration::minutes(60),
+        missing_confidence: MissingConfidence::Null,
+        anthropic_image_quality: 85,
+        image_max_dimension: 12_000,
+        image_max_decode_bytes: 512 * 1024 * 1024,
+        allow_reset: false,
+        store_raw_json: true,
+        raw_json_retention_days: None,
+        jobs: Arc::new(Mutex::new(HashMap::new())),
+        cleanup_status: Arc::new(Mutex::new(CleanupStatus::default())),
+        upload_locks: Arc::new(Mutex::new(HashMap::new())),
+    }
+}
+
+fn router(state: &Arc<AppState>) -> Router {
+    build_router(state.clone(), MediaMissing::NotFound, 64 * 1024, 10 * 1024 * 1024)
+}
+
+/// A small PNG; different seeds give different bytes, so they aren't duplicates.
+fn png(seed: u8) -> Vec<u8> {
+    let img = image::RgbImage::from_fn(16, 16, |x, y| {
+        image::Rgb([seed, (x * 16) as u8, (y * 16) as u8])
+    });
+    let mut out = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(img)
+        .write_to(&mut out, ImageFormat::Png)
+        .unwrap();
+    out.into_inner()
+}
+
+fn upload_request(image: &[u8], content_type: &str) -> Request<Body> {
+    multipart_request("/api/entries", "image", image, content_type)
+}
+
+fn multipart_request(uri: &str, name: &str, content: &[u8], content_type: &str) -> Request<Body> {
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"; filename=\"upload\"\r\nContent-Type: {content_type}\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+    Request::post(uri)
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn json_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+    (status, body)
+}
+
+async fn create(state: &Arc<AppState>, image: &[u8]) -> serde_json::Value {
+    let (status, body) = send(router(state), upload_request(image, "image/png")).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    body["entry"].clone()
+}
+
+#[sqlx::test]
+async fn create_entry_with_mock_classifier(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let image = png(1);
+
+    let entry = create(&state, &image).await;
+    assert_eq!(
+        entry["label"],
+        format!("Mock subject {}", &content_hash(&image)[..8])
+    );
+    assert_eq!(entry["tags"], serde_json::json!(["mock"]));
+    assert_eq!(entry["confidence"], 0.5);
+    assert_eq!(entry["model"], "mock");
+
+    let id = entry["id"].as_str().unwrap();
+    let (status, fetched) = send(
+        router(&state),
+        Request::get(format!("/api/entries/{id}")).body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched["label"], entry["label"]);
+    let image_path = fetched["image_url"].as_str().unwrap().trim_start_matches("/media/");
+    assert!(state.storage_dir.join(image_path).exists());
+}
+
+#[sqlx::test]
+async fn mock_classifier_covers_moderation_and_retagging(db: PgPool) {
+    let mut state = test_state(db).await;
+    state.moderation = Some(ModerationConfig {
+        prompt: "Reject anything that isn't nature.".to_string(),
+        threshold: 0.5,
+        max_tokens: 128,
+    });
+    let state = Arc::new(state);
+
+    let entry = create(&state, &png(2)).await;
+    let id = entry["id"].as_str().unwrap();
+    let (status, patched) = send(
+        router(&state),
+        json_request(
+            "PATCH",
+            &format!("/api/entries/{id}?retag=true"),
+            serde_json::json!({ "label": "Red fox" }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{patched}");
+    assert_eq!(patched["label"], "Red fox");
+    assert_eq!(patched["tags"], serde_json::json!(["mock", "retagged"]));
+}
+
+#[test]
+fn extract_json_reads_fenced_json() {
+    let text = "Here you go:\n```json\n{\"label\": \"Heron\", \"tags\": [\"bird\"]}\n```\nEnjoy!";
+    assert_eq!(
+        extract_json(text).as_deref(),
+        Some("{\"label\": \"Heron\", \"tags\": [\"bird\"]}")
+    );
+    // A fence without a language tag, and one that is never closed.
+    assert_eq!(
+        extract_json("```\n{\"label\": \"Heron\"}\n```").as_deref(),
+        Some("{\"label\": \"Heron\"}")
+    );
+    assert_eq!(
+        extract_json("```json\n{\"label\": \"Heron\"}").as_deref(),
+        Some("{\"label\": \"Heron\"}")
+    );
+}
+
+#[test]
+fn extract_json_ignores_a_trailing_sentence() {
+    let text = "{\"label\": \"Oak\", \"confidence\": 0.9} I hope this helps {or not}.";
+    assert_eq!(
+        extract_json(text).as_deref(),
+        Some("{\"label\": \"Oak\", \"confidence\": 0.9}")
+    );
+}
+
+#[test]
+fn extract_json_skips_braces_inside_strings() {
+    let text = r#"{"label": "Fern", "description": "Fronds curl like } and { in \"quotes\"."} done"#;
+    assert_eq!(
+        extract_json(text).as_deref(),
+        Some(r#"{"label": "Fern", "description": "Fronds curl like } and { in \"quotes\"."}"#)
+    );
+}
+
+#[test]
+fn extract_json_prefers_a_span_that_parses() {
+    let text = r#"I considered {label: Fern} but: {"label": "Moss"}"#;
+    assert_eq!(extract_json(text).as_deref(), Some(r#"{"label": "Moss"}"#));
+}
+
+#[test]
+fn extract_json_falls_back_when_nothing_parses_or_balances() {
+    // Balanced but not JSON: the first span comes back so the parse error can be reported.
+    assert_eq!(extract_json("{label: Fern}").as_deref(), Some("{label: Fern}"));
+    // Nothing balances: `None`, and callers parse the raw text instead.
+    assert_eq!(extract_json(r#"{"label": "Fern""#), None);
+    assert_eq!(extract_json("No JSON here."), None);
+}
+
+async fn entry_etag(state: &Arc<AppState>, id: &str) -> String {
+    let response = router(state)
+        .oneshot(Request::get(format!("/api/entries/{id}")).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.headers()[header::ETAG].to_str().unwrap().to_string()
+}
+
+#[sqlx::test]
+async fn reclassify_with_the_same_answer_keeps_the_classification(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(3)).await;
+    let id = entry["id"].as_str().unwrap();
+    let uuid = Uuid::parse_str(id).unwrap();
+    sqlx::query("UPDATE entries SET updated_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+        .bind(uuid)
+        .execute(&state.db)
+        .await
+        .unwrap();
+    let updated_at = |db: PgPool| async move {
+        sqlx::query_scalar::<_, DateTime<Utc>>("SELECT updated_at FROM entries WHERE id = $1")
+            .bind(uuid)
+            .fetch_one(&db)
+            .await
+            .unwrap()
+    };
+    let before = updated_at(state.db.clone()).await;
+    let etag = entry_etag(&state, id).await;
+
+    let (status, body) = send(
+        router(&state),
+        json_request("POST", &format!("/api/entries/{id}/reclassify"), serde_json::json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["changed"], false);
+    assert_eq!(body["changes"], serde_json::json!({}));
+    assert_eq!(body["entry"]["label"], entry["label"]);
+    assert_eq!(updated_at(state.db.clone()).await, before);
+    assert_eq!(entry_etag(&state, id).await, etag);
+}
+
+#[sqlx::test]
+async fn classify_region_needs_a_readable_stored_image(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(21)).await;
+    let id = entry["id"].as_str().unwrap();
+    let image_path = state
+        .storage_dir
+        .join(entry["image_url"].as_str().unwrap().trim_start_matches("/media/"));
+    let crop = serde_json::json!({ "x": 0, "y": 0, "width": 8, "height": 8 });
+    let uri = format!("/api/entries/{id}/classify-region");
+
+    std::fs::write(&image_path, b"not an image").unwrap();
+    let (status, body) = send(router(&state), json_request("POST", &uri, crop.clone())).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+
+    std::fs::remove_file(&image_path).unwrap();
+    let (status, body) = send(router(&state), json_request("POST", &uri, crop)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+}
+
+#[sqlx::test]
+async fn reclassify_reports_what_changed(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(4)).await;
+    let id = entry["id"].as_str().unwrap();
+    let (status, _) = send(
+        router(&state),
+        json_request(
+            "PATCH",
+            &format!("/api/entries/{id}"),
+            serde_json::json!({ "label": "Edited label", "tags": ["edited", "mock"] }),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        router(&state),
+        json_request("POST", &format!("/api/entries/{id}/reclassify"), serde_json::json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["changed"], true);
+    assert_eq!(body["changes"]["label"]["before"], "Edited label");
+    assert_eq!(body["changes"]["label"]["after"], entry["label"]);
+    assert_eq!(body["changes"]["tags"]["after"], serde_json::json!(["mock"]));
+    assert!(body["changes"].get("description").is_none());
+    assert!(body["changes"].get("confidence").is_none());
+    assert_eq!(body["entry"]["label"], entry["label"]);
+    assert_eq!(body["entry"]["edited_at"], serde_json::Value::Null);
+}
+
+#[sqlx::test]
+async fn cleanup_purges_rows_then_their_files(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let expired = create(&state, &png(5)).await;
+    let restored = create(&state, &png(6)).await;
+    for id in [&expired["id"], &restored["id"]] {
+        soft_delete(&state, id.as_str().unwrap()).await;
+    }
+    let expired_id = Uuid::parse_str(expired["id"].as_str().unwrap()).unwrap();
+    sqlx::query("UPDATE entries SET deleted_at = NOW() - INTERVAL '2 hours' WHERE id = $1")
+        .bind(expired_id)
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    assert_eq!(cleanup_deleted(&state).await.unwrap(), 1);
+    let remaining: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM entries")
+        .fetch_all(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_ne!(remaining[0], expired_id);
+    let image_path = expired["image_url"].as_str().unwrap().trim_start_matches("/media/");
+    assert!(!state.storage_dir.join(image_path).exists());
+}
+
+#[test]
+fn import_urls_must_be_public_http() {
+    for allowed in ["https://static.inaturalist.org/photos/1/large.jpg", "http://93.184.215.14/a.jpg"] {
+        assert!(check_import_url(&reqwest::Url::parse(allowed).unwrap()).is_ok(), "{allowed}");
+    }
+    for refused in [
+        "file:///etc/passwd",
+        "ftp://example.com/a.jpg",
+        "http://127.0.0.1:4000/api/health",
+        "http://10.0.0.8/a.jpg",
+        "http://169.254.169.254/latest/meta-data/",
+        "http://100.64.1.1/a.jpg",
+        "http://0.0.0.0/a.jpg",
+        "http://[::1]/a.jpg",
+        "http://[fd00::1]/a.jpg",
+        "http://[::ffff:192.168.1.1]/a.jpg",
+    ] {
+        assert!(check_import_url(&reqwest::Url::parse(refused).unwrap()).is_err(), "{refused}");
+    }
+}
+
+#[sqlx::test]
+async fn import_refuses_local_image_urls(db: PgPool) {
+    // A server that would answer with a valid image, reachable only on loopback.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let image = png(7);
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            use tokio::io::AsyncWriteExt;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                image.len()
+            );
+            let _ = socket.write_all(head.as_bytes()).await;
+            let _ = socket.write_all(&image).await;
+        }
+    });
+
+    let state = Arc::new(test_state(db).await);
+    let csv = format!(
+        "id,common_name,image_url\n1,Fox,http://127.0.0.1:{port}/fox.png\n2,Fox,http://localhost:{port}/fox.png\n"
+    );
+    let (status, body) = send(
+        router(&state),
+        multipart_request("/api/import/inaturalist", "file", csv.as_bytes(), "text/csv"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["imported"], 0);
+    assert_eq!(body["failed"], 2);
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entries")
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+/// A JPEG whose APP1 segment holds an EXIF block naming the camera make.
+fn jpeg_with_exif(make: &str) -> Vec<u8> {
+    let mut encoded = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(image::RgbImage::from_pixel(16, 16, image::Rgb([40, 120, 40])))
+        .write_to(&mut encoded, ImageFormat::Jpeg)
+        .unwrap();
+    let encoded = encoded.into_inner();
+
+    // Little-endian TIFF with a single IFD0 entry: Make, ASCII, stored inline.
+    assert!(make.len() < 4);
+    let mut value = make.as_bytes().to_vec();
+    value.resize(4, 0);
+    let mut tiff = b"II*\0\x08\0\0\0\x01\0\x0f\x01\x02\0".to_vec();
+    tiff.extend_from_slice(&((make.len() + 1) as u32).to_le_bytes());
+    tiff.extend_from_slice(&value);
+    tiff.extend_from_slice(&[0; 4]);
+
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1];
+    jpeg.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+    jpeg.extend_from_slice(b"Exif\0\0");
+    jpeg.extend_from_slice(&tiff);
+    jpeg.extend_from_slice(&encoded[2..]);
+    jpeg
+}
+
+#[sqlx::test]
+async fn import_strips_exif_like_uploads(db: PgPool) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let image = jpeg_with_exif("Fuj");
+    assert_eq!(exif::read(&image, "image/jpeg").make.as_deref(), Some("Fuj"));
+    // Stands in for the image host: every request, proxied, gets the JPEG back.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_url = format!("http://{}", listener.local_addr().unwrap());
+    let served = image.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let mut socket = BufReader::new(socket);
+            loop {
+                let mut line = String::new();
+                if socket.read_line(&mut line).await.unwrap_or(0) == 0 || line.trim().is_empty() {
+                    break;
+                }
+            }
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                served.len()
+            );
+            let _ = socket.get_mut().write_all(head.as_bytes()).await;
+            let _ = socket.get_mut().write_all(&served).await;
+        }
+    });
+
+    let mut state = test_state(db).await;
+    state.import_http = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(&proxy_url).unwrap())
+        .build()
+        .unwrap();
+    let state = Arc::new(state);
+    let csv = "id,common_name,image_url\n1,Fern,http://static.inaturalist.org/photos/1/large.jpg\n";
+    let (status, body) = send(
+        router(&state),
+        multipart_request("/api/import/inaturalist", "file", csv.as_bytes(), "text/csv"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["imported"], 1, "{body}");
+
+    let (image_path, camera_make): (String, Option<String>) =
+        sqlx::query_as("SELECT image_path, camera_make FROM entries")
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+    assert_eq!(camera_make.as_deref(), Some("Fuj"));
+    let stored = std::fs::read(state.storage_dir.join(image_path)).unwrap();
+    assert!(stored.starts_with(&[0xFF, 0xD8]));
+    assert!(!stored.windows(6).any(|window| window == b"Exif\0\0"));
+    assert_eq!(exif::read(&stored, "image/jpeg").make, None);
+}
+
+fn tags(list: &[&str]) -> Vec<String> {
+    list.iter().map(|tag| tag.to_string()).collect()
+}
+
+#[test]
+fn normalize_tags_collapses_plurals_when_enabled() {
+    assert_eq!(
+        normalize_tags(&tags(&["Trees", "tree", "forest", " Pine Trees ", "pine tree"]), true),
+        tags(&["tree", "forest", "pine tree"])
+    );
+    assert_eq!(
+        normalize_tags(&tags(&["berries", "berry", "foxes", "fox", "bushes", "bush"]), true),
+        tags(&["berry", "fox", "bush"])
+    );
+    // A lone plural keeps its spelling; words that only look plural aren't merged.
+    assert_eq!(
+        normalize_tags(&tags(&["leaves", "moss", "cactus", "species", "grass", "iris"]), true),
+        tags(&["leaves", "moss", "cactus", "species", "grass", "iris"])
+    );
+}
+
+#[test]
+fn normalize_tags_keeps_plurals_when_disabled() {
+    assert_eq!(
+        normalize_tags(&tags(&["Trees", "tree", "trees", ""]), false),
+        tags(&["trees", "tree"])
+    );
+}
+
+/// Inserts a bare entry without going through an upload.
+async fn insert_entry(db: &PgPool) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO entries (id, image_path, image_mime, label, description) \
+         VALUES ($1, $2, 'image/png', 'Test entry', '')",
+    )
+    .bind(id)
+    .bind(format!("images/{}.png", id))
+    .execute(db)
+    .await
+    .unwrap();
+    id
+}
+
+#[sqlx::test]
+async fn tag_lists_follow_the_page_size_settings(db: PgPool) {
+    let mut state = test_state(db).await;
+    state.default_page_size = 2;
+    state.max_page_size = 3;
+    let state = Arc::new(state);
+    let id = insert_entry(&state.db).await;
+    sqlx::query("UPDATE entries SET tags = ARRAY['forest', 'a', 'b', 'c', 'd', 'e'] WHERE id = $1")
+        .bind(id)
+        .execute(&state.db)
+        .await
+        .unwrap();
+
+    for uri in ["/api/tags?", "/api/stats/tags?", "/api/tags/cooccurrence?tag=forest&"] {
+        for (query, expected) in [("", 2), ("limit=1", 1), ("limit=1000", 3)] {
+            let (status, body) = send(
+                router(&state),
+                Request::get(format!("{uri}{query}")).body(Body::empty()).unwrap(),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{uri}{query}: {body}");
+            assert_eq!(body.as_array().unwrap().len(), expected, "{uri}{query}");
+        }
+    }
+}
+
+#[sqlx::test]
+async fn concurrent_pins_stay_within_the_cap(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    for _ in 0..MAX_PINNED_ENTRIES - 1 {
+        let id = insert_entry(&state.db).await;
+        sqlx::query("UPDATE entries SET is_pinned = TRUE WHERE id = $1")
+            .bind(id)
+            .execute(&state.db)
+            .await
+            .unwrap();
+    }
+    let mut candidates = Vec::new();
+    for _ in 0..6 {
+        candidates.push(insert_entry(&state.db).await);
+    }
+
+    let pins = candidates.iter().map(|id| {
+        send(
+            router(&state),
+            json_request("POST", &format!("/api/entries/{id}/pin"), serde_json::json!({ "enable": true })),
+        )
+    });
+    let statuses: Vec<StatusCode> = futures_util::future::join_all(pins)
+        .await
+        .into_iter()
+        .map(|(status, _)| status)
+        .collect();
+    assert_eq!(statuses.iter().filter(|status| **status == StatusCode::OK).count(), 1);
+    assert_eq!(
+        statuses.iter().filter(|status| **status == StatusCode::BAD_REQUEST).count(),
+        candidates.len() - 1
+    );
+    let pinned: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entries WHERE is_pinned")
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(pinned, MAX_PINNED_ENTRIES);
+}
+
+async fn set_shared(state: &Arc<AppState>, id: &str, enable: bool) -> (StatusCode, serde_json::Value) {
+    send(
+        router(state),
+        json_request("POST", &format!("/api/entries/{id}/share"), serde_json::json!({ "enable": enable })),
+    )
+    .await
+}
+
+async fn soft_delete(state: &Arc<AppState>, id: &str) {
+    let (status, _) = send(
+        router(state),
+        Request::post(format!("/api/entries/{id}/delete")).body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn changes_since(state: &Arc<AppState>, cursor: Option<&str>) -> serde_json::Value {
+    let uri = match cursor {
+        Some(cursor) => format!("/api/entries/changes?since={cursor}"),
+        None => "/api/entries/changes".to_string(),
+    };
+    let (status, body) = send(router(state), Request::get(uri).body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    body
+}
+
+#[sqlx::test]
+async fn changes_report_purged_entries_and_late_commits(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(20)).await;
+    let id = entry["id"].as_str().unwrap();
+    let first = changes_since(&state, None).await;
+    assert_eq!(first["created"][0]["id"], entry["id"]);
+    let cursor = first["cursor"].as_str().unwrap().to_string();
+    let lag = Utc::now() - DateTime::parse_from_rfc3339(&cursor).unwrap().with_timezone(&Utc);
+    assert!(lag >= Duration::seconds(CHANGES_CURSOR_LAG_SECS), "{lag}");
+
+    // Purged before the client polls again: the row is gone, its tombstone isn't.
+    soft_delete(&state, id).await;
+    sqlx::query("UPDATE entries SET deleted_at = NOW() - INTERVAL '2 hours' WHERE id = $1")
+        .bind(Uuid::parse_str(id).unwrap())
+        .execute(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(cleanup_deleted(&state).await.unwrap(), 1);
+    let second = changes_since(&state, Some(&cursor)).await;
+    assert_eq!(second["deleted"], serde_json::json!([id]));
+
+    // A write whose transaction began before that poll but committed after it.
+    let late = insert_entry(&state.db).await;
+    sqlx::query("UPDATE entries SET created_at = NOW() - INTERVAL '5 seconds', updated_at = NOW() - INTERVAL '5 seconds' WHERE id = $1")
+        .bind(late)
+        .execute(&state.db)
+        .await
+        .unwrap();
+    let third = changes_since(&state, second["cursor"].as_str()).await;
+    assert_eq!(third["created"][0]["id"], late.to_string());
+}
+
+#[sqlx::test]
+async fn soft_deleted_entries_are_not_fetched(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(8)).await;
+    let id = entry["id"].as_str().unwrap();
+    let (status, shared) = set_shared(&state, id, true).await;
+    assert_eq!(status, StatusCode::OK);
+    let token = shared["share_url"].as_str().unwrap().rsplit('/').next().unwrap().to_string();
+    let (status, _) = send(
+        router(&state),
+        Request::get(format!("/api/share/{token}")).body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    soft_delete(&state, id).await;
+    for uri in [format!("/api/entries/{id}"), format!("/api/share/{token}")] {
+        let (status, _) = send(router(&state), Request::get(&uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{uri}");
+    }
+}
+
+#[sqlx::test]
+async fn soft_deleted_entries_cannot_be_shared(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(9)).await;
+    let id = entry["id"].as_str().unwrap();
+    soft_delete(&state, id).await;
+
+    for enable in [true, false] {
+        let (status, _) = set_shared(&state, id, enable).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+    let token: Option<String> = sqlx::query_scalar("SELECT share_token FROM entries WHERE id = $1")
+        .bind(Uuid::parse_str(id).unwrap())
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(token, None);
+}
+
+#[sqlx::test]
+async fn share_token_collisions_are_regenerated(db: PgPool) {
+    let state = test_state(db).await;
+    let taken = insert_entry(&state.db).await;
+    sqlx::query("UPDATE entries SET share_token = 'taken-token' WHERE id = $1")
+        .bind(taken)
+        .execute(&state.db)
+        .await
+        .unwrap();
+    let id = insert_entry(&state.db).await;
+
+    let mut tokens = ["taken-token", "taken-token", "fresh-token"].into_iter();
+    let token = assign_share_token_with(&state.db, id, || tokens.next().unwrap().to_string())
+        .await
+        .unwrap();
+    assert_eq!(token, "fresh-token");
+    assert_eq!(tokens.next(), None);
+
+    // Every attempt colliding is an error, not a silently unshared entry.
+    let other = insert_entry(&state.db).await;
+    let err = assign_share_token_with(&state.db, other, || "taken-token".to_string())
+        .await
+        .unwrap_err();
+    assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[sqlx::test]
+async fn resharing_keeps_the_existing_token(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let entry = create(&state, &png(10)).await;
+    let id = entry["id"].as_str().unwrap();
+
+    let (_, first) = set_shared(&state, id, true).await;
+    let (status, second) = set_shared(&state, id, true).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(first["share_url"].is_string());
+    assert_eq!(second["share_url"], first["share_url"]);
+
+    // Turning sharing off and on again is what revokes a link.
+    set_shared(&state, id, false).await;
+    let (_, third) = set_shared(&state, id, true).await;
+    assert_ne!(third["share_url"], first["share_url"]);
+}
+
+#[sqlx::test]
+async fn media_answers_range_requests(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let image = png(11);
+    std::fs::write(state.storage_dir.join("images/range.png"), &image).unwrap();
+
+    let response = router(&state)
+        .oneshot(
+            Request::get("/media/images/range.png")
+                .header(header::RANGE, "bytes=0-9")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()[header::CONTENT_RANGE],
+        format!("bytes 0-9/{}", image.len()).as_str()
+    );
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], &image[..10]);
+}
+
+#[sqlx::test]
+async fn octet_stream_png_uploads_are_stored_as_png(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let (status, body) = send(
+        router(&state),
+        upload_request(&png(12), "application/octet-stream"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let id = Uuid::parse_str(body["entry"]["id"].as_str().unwrap()).unwrap();
+    let (mime, path): (String, String) =
+        sqlx::query_as("SELECT image_mime, image_path FROM entries WHERE id = $1")
+            .bind(id)
+            .fetch_one(&state.db)
+            .await
+            .unwrap();
+    assert_eq!(mime, "image/png");
+    assert!(path.ends_with(".png"), "{path}");
+    // The classifier was sent the PNG as a PNG too.
+    let description = body["entry"]["description"].as_str().unwrap();
+    assert!(description.starts_with("A image/png image"), "{description}");
+}
+
+#[sqlx::test]
+async fn uploads_in_unsupported_formats_are_refused(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"16\" height=\"16\"/>";
+    let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+    for (bytes, content_type) in [
+        (&svg[..], "image/svg+xml"),
+        (&heic[..], "image/heic"),
+        (&heic[..], "application/octet-stream"),
+    ] {
+        let (status, body) = send(router(&state), upload_request(bytes, content_type)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{content_type}: {body}");
+    }
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entries")
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+/// A PNG whose header claims `width` x `height` pixels, followed by a token amount of data.
+fn png_claiming(width: u32, height: u32) -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let mut crc = crc32fast::Hasher::new();
+        crc.update(kind);
+        crc.update(data);
+        out.extend_from_slice(&crc.finalize().to_be_bytes());
+    }
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::new();
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &[0x78, 0x9c, 0x03, 0x00, 0x00, 0x00, 0x00, 0x01]);
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[sqlx::test]
+async fn uploads_declaring_huge_dimensions_are_rejected(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    // 60000 x 60000 RGB would need about 10 GB once decoded.
+    for (width, height) in [(60_000, 60_000), (12_001, 10)] {
+        let (status, body) = send(
+            router(&state),
+            upload_request(&png_claiming(width, height), "image/png"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{width}x{height}: {body}");
+        assert!(body["error"].as_str().unwrap().contains("limit"), "{body}");
+    }
+    // Within IMAGE_MAX_DIMENSION but over IMAGE_MAX_DECODE_MB.
+    let mut state = test_state(state.db.clone()).await;
+    state.image_max_decode_bytes = 1024 * 1024;
+    let state = Arc::new(state);
+    let (status, body) = send(
+        router(&state),
+        upload_request(&png_claiming(2_000, 2_000), "image/png"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    assert!(body["error"].as_str().unwrap().contains("limit"), "{body}");
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entries")
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[sqlx::test]
+async fn simultaneous_identical_uploads_create_one_entry(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    let image = png(13);
+
+    let uploads = (0..4).map(|_| send(router(&state), upload_request(&image, "image/png")));
+    let results = futures_util::future::join_all(uploads).await;
+    let ids: HashSet<&str> = results
+        .iter()
+        .map(|(status, body)| {
+            assert_eq!(*status, StatusCode::OK, "{body}");
+            body["entry"]["id"].as_str().unwrap()
+        })
+        .collect();
+    assert_eq!(ids.len(), 1);
+    assert_eq!(
+        results.iter().filter(|(_, body)| body["duplicate"] == false).count(),
+        1
+    );
+
+    // The same goes for retries sharing an `Idempotency-Key`, even with `force`.
+    let retries = (0..3).map(|_| {
+        let mut request = upload_request(&png(14), "image/png");
+        *request.uri_mut() = "/api/entries?force=true".parse().unwrap();
+        request
+            .headers_mut()
+            .insert("idempotency-key", "retry-1".parse().unwrap());
+        send(router(&state), request)
+    });
+    let retried: HashSet<String> = futures_util::future::join_all(retries)
+        .await
+        .into_iter()
+        .map(|(status, body)| {
+            assert_eq!(status, StatusCode::OK, "{body}");
+            body["entry"]["id"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(retried.len(), 1);
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM entries")
+        .fetch_one(&state.db)
+        .await
+        .unwrap();
+    assert_eq!(count, 2);
+}
+
+/// A stand-in HTTP proxy recording each request line and how many connections carried them.
+/// Plain requests get an empty `200`; `CONNECT` tunnels are refused.
+struct RecordingProxy {
+    url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+    connections: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+async fn recording_proxy() -> RecordingProxy {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = RecordingProxy {
+        url: format!("http://{}", listener.local_addr().unwrap()),
+        requests: Arc::default(),
+        connections: Arc::default(),
+    };
+    let (requests, connections) = (proxy.requests.clone(), proxy.connections.clone());
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            connections.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let requests = requests.clone();
+            tokio::spawn(async move {
+                let mut socket = BufReader::new(socket);
+                loop {
+                    let mut request_line = String::new();
+                    if socket.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        socket.read_line(&mut line).await.unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = line.split_once(':')
+                            && name.eq_ignore_ascii_case("content-length")
+                        {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                    socket.read_exact(&mut vec![0; content_length]).await.unwrap();
+                    let tunnel = request_line.starts_with("CONNECT");
+                    requests.lock().unwrap().push(request_line.trim().to_string());
+                    let response: &[u8] = match tunnel {
+                        true => b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        false => b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+                    };
+                    socket.get_mut().write_all(response).await.unwrap();
+                    if tunnel {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    proxy
+}
+
+#[sqlx::test]
+async fn outgoing_calls_share_the_client_in_state(db: PgPool) {
+    let proxy = recording_proxy().await;
+    let mut state = test_state(db).await;
+    // Only requests made through this very client can reach the proxy: the hosts below
+    // don't resolve, and a client built per call wouldn't know about the proxy.
+    state.http = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(&proxy.url).unwrap())
+        .build()
+        .unwrap();
+    state.classifier = Arc::new(AnthropicClassifier);
+    state.webhook = Some(WebhookConfig {
+        url: "http://hooks.naturadex.invalid/events".to_string(),
+        events: vec!["entry.deleted".to_string()],
+    });
+    let state = Arc::new(state);
+
+    for expected in 1..=2 {
+        notify_webhook(&state, "entry.deleted", Uuid::new_v4(), "user");
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while proxy.requests.lock().unwrap().len() < expected {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+    // Both webhooks went over one pooled keep-alive connection.
+    assert_eq!(proxy.connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    let err = retag_from_text(&state, "Red fox", "A fox in the snow.").await.unwrap_err();
+    assert_eq!(err.status, StatusCode::BAD_GATEWAY, "{}", err.message);
+    assert_eq!(
+        *proxy.requests.lock().unwrap(),
+        [
+            "POST http://hooks.naturadex.invalid/events HTTP/1.1",
+            "POST http://hooks.naturadex.invalid/events HTTP/1.1",
+            "CONNECT api.anthropic.com:443 HTTP/1.1",
+        ]
+    );
+}
+
+#[sqlx::test]
+async fn pdf_export_streams_a_well_formed_document(db: PgPool) {
+    let state = Arc::new(test_state(db).await);
+    create(&state, &png(15)).await;
+    create(&state, &png(16)).await;
+
+    let response = router(&state)
+        .oneshot(Request::get("/api/export.pdf").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/pdf");
+    assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+    let pdf = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert!(pdf.starts_with(b"%PDF-1.4\n"));
+    assert!(pdf.ends_with(b"%%EOF\n"));
+
+    // Every cross-reference entry points at the object it numbers. Offsets are in bytes,
+    // so the (binary) file is only decoded from the table on.
+    let tail = String::from_utf8_lossy(&pdf[pdf.len() - 32..]);
+    let startxref: usize = tail.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse().unwrap();
+    let xref = std::str::from_utf8(&pdf[startxref..]).unwrap();
+    assert!(xref.starts_with("xref\n0 "));
+    let offsets: Vec<usize> = xref
+        .lines()
+        .skip(3)
+        .take_while(|line| !line.starts_with("trailer"))
+        .map(|line| line[..10].parse().unwrap())
+        .collect();
+    for (index, offset) in offsets.iter().enumerate() {
+        let object = format!("{} 0 obj\n", index + 1);
+        assert_eq!(&pdf[*offset..*offset + object.len()], object.as_bytes());
+    }
+    let text = String::from_utf8_lossy(&pdf);
+    assert!(text.contains("/Type /Pages /Kids ["));
+    assert_eq!(text.matches("/Type /Page ").count(), 3);
+    assert_eq!(text.matches("/Subtype /Image").count(), 2);
+}
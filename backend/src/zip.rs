@@ -0,0 +1,105 @@
+//! Minimal writer for uncompressed ("stored") zip archives.
+//!
+//! Exports bundle already-compressed images, so deflate would buy little; storing
+//! keeps this small enough to not need a dependency.
+
+use chrono::{Datelike, Timelike, Utc};
+
+struct CentralRecord {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+pub struct ZipWriter {
+    buf: Vec<u8>,
+    records: Vec<CentralRecord>,
+    dos_time: u16,
+    dos_date: u16,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        let now = Utc::now();
+        let dos_time = ((now.hour() << 11) | (now.minute() << 5) | (now.second() / 2)) as u16;
+        let dos_date = (((now.year() - 1980).max(0) as u32) << 9 | now.month() << 5 | now.day()) as u16;
+        Self {
+            buf: Vec::new(),
+            records: Vec::new(),
+            dos_time,
+            dos_date,
+        }
+    }
+
+    pub fn add_file(&mut self, name: &str, data: &[u8]) {
+        let crc = crc32fast::hash(data);
+        let size = data.len() as u32;
+        let offset = self.buf.len() as u32;
+
+        self.put_u32(0x0403_4b50);
+        self.put_u16(20); // version needed to extract
+        self.put_u16(0x0800); // flags: UTF-8 names
+        self.put_u16(0); // method: stored
+        self.put_u16(self.dos_time);
+        self.put_u16(self.dos_date);
+        self.put_u32(crc);
+        self.put_u32(size);
+        self.put_u32(size);
+        self.put_u16(name.len() as u16);
+        self.put_u16(0); // extra field length
+        self.buf.extend_from_slice(name.as_bytes());
+        self.buf.extend_from_slice(data);
+
+        self.records.push(CentralRecord {
+            name: name.to_string(),
+            crc,
+            size,
+            offset,
+        });
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let directory_offset = self.buf.len() as u32;
+        let records = std::mem::take(&mut self.records);
+        for record in &records {
+            self.put_u32(0x0201_4b50);
+            self.put_u16(20); // version made by
+            self.put_u16(20); // version needed to extract
+            self.put_u16(0x0800);
+            self.put_u16(0);
+            self.put_u16(self.dos_time);
+            self.put_u16(self.dos_date);
+            self.put_u32(record.crc);
+            self.put_u32(record.size);
+            self.put_u32(record.size);
+            self.put_u16(record.name.len() as u16);
+            self.put_u16(0); // extra field length
+            self.put_u16(0); // comment length
+            self.put_u16(0); // disk number
+            self.put_u16(0); // internal attributes
+            self.put_u32(0); // external attributes
+            self.put_u32(record.offset);
+            self.buf.extend_from_slice(record.name.as_bytes());
+        }
+        let directory_size = self.buf.len() as u32 - directory_offset;
+
+        self.put_u32(0x0605_4b50);
+        self.put_u16(0); // this disk
+        self.put_u16(0); // disk with the central directory
+        self.put_u16(records.len() as u16);
+        self.put_u16(records.len() as u16);
+        self.put_u32(directory_size);
+        self.put_u32(directory_offset);
+        self.put_u16(0); // comment length
+        self.buf
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+}
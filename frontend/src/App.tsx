@@ -1,4 +1,4 @@
-import { useEffect, useMemo, useRef, useState, type ChangeEvent } from 'react'
+import { useEffect, useMemo, useRef, useState, type ChangeEvent, type SyntheticEvent } from 'react'
 import { apiClient } from './api'
 import type { EntryDetail, EntrySummary, Health, Settings } from './types'
 import './App.css'
@@ -19,6 +19,16 @@ const formatDate = (iso: string) => {
   })
 }
 
+// Grids show thumbnails; entries without one, or whose thumbnail fails to load, use the original.
+const gridImage = (entry: EntrySummary) => entry.thumb_url ?? entry.image_url
+
+const fallBackToOriginal = (entry: EntrySummary) => (event: SyntheticEvent<HTMLImageElement>) => {
+  const img = event.currentTarget
+  if (img.dataset.fallback) return
+  img.dataset.fallback = 'true'
+  img.src = entry.image_url
+}
+
 const confidenceLabel = (value?: number | null) => {
   if (value === null || value === undefined) return 'Confidence: --'
   return `Confidence: ${Math.round(value * 100)}%`
@@ -110,6 +120,7 @@ function App() {
   const [publicMode, setPublicMode] = useState(false)
   const [publicEntries, setPublicEntries] = useState<EntrySummary[]>([])
   const [publicError, setPublicError] = useState<string | null>(null)
+  const [publicEmpty, setPublicEmpty] = useState<string | null>(null)
   const [celebrate, setCelebrate] = useState(false)
 
   const videoRef = useRef<HTMLVideoElement | null>(null)
@@ -179,7 +190,12 @@ function App() {
     const loadPublic = async () => {
       try {
         const data = await apiClient.listPublicEntries()
-        if (active) setPublicEntries(data)
+        if (!active) return
+        setPublicEntries(data)
+        if (data.length === 0) {
+          const meta = await apiClient.getPublicMeta()
+          if (active) setPublicEmpty(meta.empty_message ?? 'Nothing has been shared yet.')
+        }
       } catch (err) {
         if (active) setPublicError('This dex is private right now.')
       }
@@ -337,10 +353,10 @@ function App() {
   const togglePublic = async () => {
     if (!settings) return
     const previous = settings
-    const next = { is_public: !settings.is_public }
+    const next = { ...settings, is_public: !settings.is_public }
     setSettings(next)
     try {
-      await apiClient.updateSettings(next)
+      await apiClient.updateSettings({ is_public: next.is_public })
     } catch (err) {
       setSettings(previous)
       setStatus('Failed to update dex visibility.')
@@ -423,9 +439,9 @@ function App() {
             <div className="public-grid">
               {publicEntries.map((entry, index) => (
                 <div key={entry.id} className="public-card">
-                  <img src={entry.image_url} alt={entry.label} />
+                  <img src={gridImage(entry)} alt={entry.label} onError={fallBackToOriginal(entry)} />
                   <div className="public-info" style={{ ['--delay' as string]: `${index * 60}ms` }}>
-                    <h3>{entry.label}</h3>
+                    <h3>{entry.display_title ?? entry.label}</h3>
                     <p>{entry.description}</p>
                   </div>
                 </div>
@@ -433,7 +449,7 @@ function App() {
             </div>
           ) : (
             <div className="share-body">
-              <p>{publicError ?? 'Loading public collection...'}</p>
+              <p>{publicError ?? publicEmpty ?? 'Loading public collection...'}</p>
             </div>
           )}
           <button className="btn ghost" onClick={() => (window.location.href = '/')}>
@@ -649,9 +665,14 @@ function App() {
                 onClick={() => selectEntry(entry)}
                 style={{ ['--delay' as string]: `${index * 60}ms` }}
               >
-                <img src={entry.image_url} alt={entry.label} loading="lazy" />
+                <img
+                  src={gridImage(entry)}
+                  alt={entry.label}
+                  loading="lazy"
+                  onError={fallBackToOriginal(entry)}
+                />
                 <div className="entry-info">
-                  <h3>{entry.label}</h3>
+                  <h3>{entry.display_title ?? entry.label}</h3>
                   <p>{entry.description}</p>
                   <div className="entry-meta">
                     <span>{formatDate(entry.created_at)}</span>
@@ -666,6 +687,12 @@ function App() {
               <div>
                 <h3>{selectedEntry.label}</h3>
                 <p>{selectedEntry.description}</p>
+                {selectedEntry.reasoning && <p className="meta-line">Why: {selectedEntry.reasoning}</p>}
+                {selectedEntry.alternatives && selectedEntry.alternatives.length > 0 && (
+                  <p className="meta-line">
+                    Could also be: {selectedEntry.alternatives.map((alt) => alt.label).join(', ')}
+                  </p>
+                )}
                 <div className="tag-row">
                   {selectedEntry.tags.map((tag) => (
                     <span key={tag} className="tag">
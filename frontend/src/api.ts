@@ -1,4 +1,4 @@
-import type { CreateEntryResponse, EntryDetail, EntrySummary, Health, Settings } from './types'
+import type { CreateEntryResponse, EntryDetail, EntrySummary, Health, PublicMeta, Settings } from './types'
 
 const API_BASE = import.meta.env.VITE_API_BASE ?? ''
 
@@ -11,17 +11,30 @@ async function api<T>(path: string, options?: RequestInit): Promise<T> {
   return res.json() as Promise<T>
 }
 
+// The list endpoints page their results; fetch pages until a short one comes back.
+const PAGE_SIZE = 200
+
+async function listAll(path: string): Promise<EntrySummary[]> {
+  const entries: EntrySummary[] = []
+  for (let offset = 0; ; offset += PAGE_SIZE) {
+    const page = await api<EntrySummary[]>(`${path}?limit=${PAGE_SIZE}&offset=${offset}`)
+    entries.push(...page)
+    if (page.length < PAGE_SIZE) return entries
+  }
+}
+
 export const apiClient = {
   health: () => api<Health>('/api/health'),
   getSettings: () => api<Settings>('/api/settings'),
-  updateSettings: (payload: Settings) =>
+  updateSettings: (payload: Partial<Settings>) =>
     api<Settings>('/api/settings', {
       method: 'PUT',
       headers: { 'Content-Type': 'application/json' },
       body: JSON.stringify(payload),
     }),
-  listEntries: () => api<EntrySummary[]>('/api/entries'),
-  listPublicEntries: () => api<EntrySummary[]>('/api/public/entries'),
+  listEntries: () => listAll('/api/entries'),
+  listPublicEntries: () => listAll('/api/public/entries'),
+  getPublicMeta: () => api<PublicMeta>('/api/public/meta'),
   getEntry: (id: string) => api<EntryDetail>(`/api/entries/${id}`),
   getSharedEntry: (token: string) => api<EntryDetail>(`/api/share/${token}`),
   createEntry: (formData: FormData) =>
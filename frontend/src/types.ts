@@ -2,7 +2,9 @@ export type EntrySummary = {
   id: string
   created_at: string
   image_url: string
+  thumb_url?: string | null
   label: string
+  display_title?: string
   description: string
   confidence?: number | null
   tags: string[]
@@ -11,10 +13,19 @@ export type EntrySummary = {
 
 export type EntryDetail = EntrySummary & {
   share_url?: string | null
+  reasoning?: string | null
+  alternatives?: { label: string; confidence?: number | null }[]
 }
 
 export type Settings = {
   is_public: boolean
+  auto_share: boolean
+  empty_message?: string | null
+}
+
+export type PublicMeta = {
+  entry_count: number
+  empty_message: string | null
 }
 
 export type Health = {